@@ -9,7 +9,7 @@ cd nimble-miner-public/
 # echo "CUDA_VISIBLE_DEVICES=$nivdai_card_number make run addr=$2";
 
 # 怎么执行
-# execute.sh <restart|start> <service_name> <card_num> <addres> 
+# execute.sh <restart|start|stop> <card_num> <addres> 
 # source ~/.bashrc
 # conda init
 # conda activate nimble
@@ -29,8 +29,12 @@ case $action in
         echo "action:${action} service_name:${service_name}"
         pm2 restart ${service_name} 
         ;; 
+    "stop")
+        echo "action:${action} service_name:${service_name}"
+        pm2 delete ${service_name}
+        ;; 
     * ) 
-        echo "useage:bash execute.sh <restart|start> <service_name> <card_num> <addres>";
+        echo "useage:bash execute.sh <restart|start|stop> <card_num> <addres>";
         ;; 
 esac
 
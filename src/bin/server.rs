@@ -1,11 +1,22 @@
 use actix_web::{App, HttpServer};
+use clap::Parser;
+use monitor::config::{Config, ConfigSource, CONFIG};
 use monitor::server::address::pool;
-use monitor::server::distribute_address;
-use monitor::server::printlnlog;
+use monitor::server::{distribute_address, heartbeat, printlnlog};
+use monitor::shutdown;
+use std::sync::Arc;
 use time::macros::format_description;
 use time::UtcOffset;
+use tracing::{error, info};
 use tracing_subscriber::fmt::time::OffsetTime;
 
+#[derive(Parser, Debug)]
+#[command(version, about = "clore 租用调度服务", long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    source: ConfigSource,
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let local_time = OffsetTime::new(
@@ -13,19 +24,43 @@ async fn main() -> std::io::Result<()> {
         format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"),
     );
     tracing_subscriber::fmt().with_timer(local_time).init();
+    let cli = Cli::parse();
+    if let Err(e) = Config::init(cli.source.or_env()).await {
+        eprintln!("配置加载失败:\n{}", e);
+        std::process::exit(2);
+    }
 
-    pool().await;
-    // let mut tasks: Vec<_> = Vec::new();
-    // tasks.push(tokio::spawn(pool()));
-
-    // let _ = HttpServer::new(|| App::new().service(distribute_address).service(printlnlog))
-    //     .bind(("0.0.0.0", 8888))?
-    //     .run()
-    //     .await;
+    tokio::spawn(shutdown::listen());
+    tokio::spawn(Config::watch());
+    let port = {
+        let config = Arc::clone(&CONFIG);
+        let config_locked = config.lock().await;
+        config_locked.server.port.unwrap_or(8888) as u16
+    };
+    let task = tokio::spawn(pool());
 
-    // for task in tasks.into_iter() {
-    //     task.abort();
-    // }
+    // 接收节点日志、心跳,分配节点地址
+    let server = HttpServer::new(|| {
+        App::new()
+            .service(printlnlog)
+            .service(heartbeat)
+            .service(distribute_address)
+    })
+    // 退出信号由 shutdown::listen 统一处理,调度完成当前周期后再退出
+    .disable_signals()
+    .bind(("0.0.0.0", port))?
+    .run();
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown::wait().await;
+        handle.stop(true).await;
+    });
+    let result = server.await;
 
-    Ok(())
+    shutdown::trigger();
+    if let Err(e) = task.await {
+        error!("调度任务异常退出:{}", e);
+    }
+    info!("调度服务已退出");
+    result
 }
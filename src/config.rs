@@ -1,110 +1,1009 @@
 use std::{
-    any::{self, Any},
-    fs::OpenOptions,
-    io::{BufReader, BufWriter, Read, Write},
+    collections::BTreeMap,
     net::IpAddr,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::SystemTime,
 };
 
+use config::{Environment, File, FileFormat};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use self::secret::Secret;
+use crate::monitor::nvidia::GpuBackendKind;
+use crate::monitor::supervisor::SupervisorKind;
+use crate::server::clore::model::CardType;
+
+pub mod check;
+pub mod secret;
+pub mod snapshot;
 
 lazy_static! {
+    static ref CONFIG_SOURCE: std::sync::Mutex<ConfigSource> =
+        std::sync::Mutex::new(ConfigSource::from_env());
     pub static ref CONFIG: Arc<Mutex<Config>> = Arc::new(Mutex::new(Config::new()));
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// 默认配置文件名
+pub const CONFIG_FILE: &str = ".conf.toml";
+/// 默认敏感配置文件名,与配置文件同目录,权限须为 0600
+pub const SECRETS_FILE: &str = ".secrets.toml";
+/// 环境变量前缀,如:MONITOR__CLORE__API_TOKEN
+pub const ENV_PREFIX: &str = "MONITOR";
+/// 覆盖 monitor.heartbeat.secret 的环境变量,中控下单时传给租用的节点
+pub const HEARTBEAT_SECRET_ENV: &str = "MONITOR__MONITOR__HEARTBEAT__SECRET";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Address {
     pub mst_address: Vec<String>,
     pub sub_address: Vec<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Monitor {
     pub api_report_log: String,
+    /// 挖矿进程管理方式:pm2、systemd 或 native
+    #[serde(default)]
+    pub supervisor: SupervisorKind,
+    #[serde(default)]
+    pub restart: Restart,
+    /// 节点http服务端口,默认 8888
+    pub http_port: Option<u16>,
+    /// 挖矿进度超过该时长(秒)无变化视为卡住并重启,默认 600
+    pub stall_after: Option<i64>,
+    /// 环境安装阶段超过该时长(秒)无进展视为卡住并重启,默认 3600
+    pub install_stall_after: Option<i64>,
+    #[serde(default)]
+    pub heartbeat: Heartbeat,
+    #[serde(default)]
+    pub host: Host,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    #[serde(default)]
+    pub gpu: Gpu,
+    /// 收到 SIGTERM/SIGINT 退出时对挖矿进程的处理,默认保留
+    #[serde(default)]
+    pub on_shutdown: ShutdownAction,
+}
+
+impl Monitor {
+    /// 中控接口地址,与 api_report_log 同一服务
+    pub fn central_url(&self, path: &str) -> String {
+        match self.api_report_log.rsplit_once('/') {
+            Some((base, _)) => format!("{}/{}", base, path),
+            None => format!("{}/{}", self.api_report_log, path),
+        }
+    }
+
+    /// 心跳上报地址,未配置时与 api_report_log 同一服务
+    pub fn heartbeat_url(&self) -> String {
+        match &self.heartbeat.api {
+            Some(api) => api.clone(),
+            None => self.central_url("heartbeat"),
+        }
+    }
 }
 
+/// 主机异常时的处理方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostAction {
+    /// 清理 cache_dirs 中的缓存
+    Clean,
+    /// 停止本机全部挖矿进程
+    StopMiners,
+    /// 随心跳标记主机异常并上报
+    Flag,
+}
+
+/// 节点程序退出时对挖矿进程的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownAction {
+    /// 保留挖矿进程继续运行,程序重启后重新接管
+    #[default]
+    Detach,
+    /// 停止本机全部挖矿进程
+    Stop,
+}
+
+/// 主机健康检查:磁盘、内存、负载及 OOM
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Host {
+    /// 检查间隔(秒)
+    pub interval: u64,
+    /// 挖矿目录所在磁盘最少剩余空间(MB)
+    pub min_disk_free: u64,
+    /// 最少可用内存(MB)
+    pub min_mem_available: u64,
+    /// 每核最高1分钟平均负载
+    pub max_load: f32,
+    /// 磁盘空间不足时清理的缓存目录,~ 为用户目录,只允许 ~/.cache 或工作目录下的子目录
+    pub cache_dirs: Vec<String>,
+    pub on_disk_full: Vec<HostAction>,
+    pub on_low_memory: Vec<HostAction>,
+    pub on_overload: Vec<HostAction>,
+    pub on_oom_kill: Vec<HostAction>,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            interval: 60,
+            min_disk_free: 10240,
+            min_mem_available: 2048,
+            max_load: 4f32,
+            cache_dirs: Vec::new(),
+            on_disk_full: vec![HostAction::Flag],
+            on_low_memory: vec![HostAction::Flag],
+            on_overload: vec![HostAction::Flag],
+            on_oom_kill: vec![HostAction::Flag],
+        }
+    }
+}
+
+/// 显卡信息来源
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gpu {
+    /// auto(优先 NVML,不可用时解析 nvidia-smi)、nvml、smi 或 fixture
+    pub backend: GpuBackendKind,
+    /// backend 为 fixture 时读取的文件,.json 为录制的显卡信息,其余按 `nvidia-smi -L` 输出解析
+    pub fixture: Option<PathBuf>,
+}
+
+/// 显卡运行数据采集:温度、功耗、利用率、显存、降频及 ECC
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    /// 采集间隔(秒)
+    pub interval: u64,
+    /// 每张显卡保留的采样数量,心跳上报其汇总
+    pub samples: usize,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry {
+            interval: 10,
+            samples: 60,
+        }
+    }
+}
+
+/// 节点向中控上报的心跳
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Heartbeat {
+    /// 上报地址,默认由 api_report_log 推出
+    pub api: Option<String>,
+    /// 上报间隔(秒)
+    pub interval: u64,
+    /// 签名密钥,节点与中控需一致,未配置时不上报
+    pub secret: Secret<String>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat {
+            api: None,
+            interval: 60,
+            secret: Secret::default(),
+        }
+    }
+}
+
+/// 日志触发重启(初始化失败、算力过低)的处理策略
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Restart {
+    /// 同一地址两次重启的最小间隔(秒)
+    pub cooldown: i64,
+    /// 同一地址每小时最多重启次数,超出后上报并暂停处理一小时
+    pub max_per_hour: usize,
+    /// 超出重启次数时停止该地址的挖矿进程
+    pub stop_on_escalate: bool,
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Restart {
+            cooldown: 300,
+            max_per_hour: 3,
+            stop_on_escalate: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Server {
     pub ip: Option<IpAddr>,
     pub port: Option<u32>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Clore {
     pub web_api_host: String,
-    pub web_token: String,
+    #[serde(default)]
+    pub web_token: Secret<String>,
     pub api_host: String,
-    pub api_token: String,
-    pub ssh_passwd: String,
+    #[serde(default)]
+    pub api_token: Secret<String>,
+    #[serde(default)]
+    pub ssh_passwd: Secret<String>,
     pub command: String,
+    /// 多个clore账户,未配置时使用上面的token作为 default 账户
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+}
+
+/// clore账户,token/密码/启动命令未配置时使用 `[clore]` 下的同名配置
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub name: String,
+    #[serde(default)]
+    pub web_token: Secret<String>,
+    #[serde(default)]
+    pub api_token: Secret<String>,
+    #[serde(default)]
+    pub ssh_passwd: Secret<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+    /// 由该账户租用服务器的子地址,未分配给任何账户的子地址归第一个账户
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+impl Clore {
+    /// 补全默认值后的全部账户
+    pub fn get_accounts(&self) -> Vec<Account> {
+        if self.accounts.is_empty() {
+            return vec![Account {
+                name: "default".to_string(),
+                web_token: self.web_token.clone(),
+                api_token: self.api_token.clone(),
+                ssh_passwd: self.ssh_passwd.clone(),
+                command: Some(self.command.clone()),
+                addresses: Vec::new(),
+            }];
+        }
+        self.accounts
+            .iter()
+            .map(|account| {
+                let mut account = account.clone();
+                if account.web_token.expose().is_empty() {
+                    account.web_token = self.web_token.clone();
+                }
+                if account.api_token.expose().is_empty() {
+                    account.api_token = self.api_token.clone();
+                }
+                if account.ssh_passwd.expose().is_empty() {
+                    account.ssh_passwd = self.ssh_passwd.clone();
+                }
+                if account.command.is_none() {
+                    account.command = Some(self.command.clone());
+                }
+                account
+            })
+            .collect()
+    }
+
+    pub fn get_account(&self, name: &str) -> Option<Account> {
+        self.get_accounts()
+            .into_iter()
+            .find(|account| account.name == name)
+    }
+
+    /// 子地址所属账户名称
+    pub fn account_of(&self, address: &str) -> String {
+        let accounts = self.get_accounts();
+        accounts
+            .iter()
+            .find(|account| account.addresses.iter().any(|addr| addr == address))
+            .or(accounts.first())
+            .map(|account| account.name.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// 租用及健康检查策略,未配置的项使用默认值
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Strategy {
+    /// 调度轮询间隔(秒)
+    pub interval: u64,
+    /// 每台服务器分配的地址数量,即租用服务器的显卡数量
+    pub chunk_size: usize,
+    /// 超时未上报日志时自动取消订单,默认关闭
+    pub auto_cancel: bool,
+    /// 下单后超过该时长(分钟)仍未上报日志,取消订单
+    pub deploying_timeout: i64,
+    /// 已部署的服务器超过该时长(分钟)未上报日志,取消订单
+    pub report_timeout: i64,
+    pub market: Market,
+    /// 各型号单卡每天的最高出价(CLORE),未列出的型号不租用
+    pub max_price: BTreeMap<String, f64>,
+    pub hashrate: Hashrate,
 }
 
+/// 市场服务器筛选条件
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Market {
+    /// 最低用户评分(不含)
+    pub min_rating: f32,
+    /// 最短可租时长(小时,不含)
+    pub min_mrl: u32,
+    /// 最低下行带宽(Mbps,不含)
+    pub min_net_down: f64,
+    /// 最少cpu核数
+    pub min_cpus: u32,
+    /// 显卡型号正则
+    pub gpu_regex: String,
+    /// cpu型号正则
+    pub cpu_regex: String,
+    /// 允许租用的显卡型号
+    pub card_types: Vec<CardType>,
+}
+
+/// 挖矿算力(it/s)判定阈值
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hashrate {
+    /// 低于该值视为算力异常,需要重启
+    pub low: f32,
+    /// 高于该值视为验算阶段,不计入算力
+    pub verify: f32,
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        let max_price = [
+            (CardType::NVIDIA4090, 32f64),
+            (CardType::NVIDIA4080S, 24f64),
+            (CardType::NVIDIA4080, 20f64),
+            (CardType::NVIDIA4070S, 32f64),
+            (CardType::NVIDIA4070, 17f64),
+            (CardType::NVIDIA4070TI, 17f64),
+            (CardType::NVIDIA3090, 19f64),
+            (CardType::NVIDIA3090TI, 19f64),
+            (CardType::NVIDIA3080TI, 15f64),
+            (CardType::NVIDIA3080, 15f64),
+            (CardType::NVIDIA1080TI, 10f64),
+            (CardType::NVIDIA1080, 10f64),
+        ];
+        Strategy {
+            interval: 30,
+            chunk_size: 2,
+            auto_cancel: false,
+            deploying_timeout: 25,
+            report_timeout: 10,
+            market: Market::default(),
+            max_price: max_price
+                .into_iter()
+                .map(|(card_type, price)| (card_type.to_string(), price))
+                .collect(),
+            hashrate: Hashrate::default(),
+        }
+    }
+}
+
+impl Default for Market {
+    fn default() -> Self {
+        Market {
+            min_rating: 3f32,
+            min_mrl: 72,
+            min_net_down: 25f64,
+            min_cpus: 8,
+            gpu_regex: r"(3080|3090|4070|4080|4090)".to_string(),
+            cpu_regex: r"((?i)ryzen|intel)".to_string(),
+            card_types: vec![CardType::NVIDIA4090],
+        }
+    }
+}
+
+impl Default for Hashrate {
+    fn default() -> Self {
+        Hashrate {
+            low: 11f32,
+            verify: 20f32,
+        }
+    }
+}
+
+impl Strategy {
+    pub async fn get_config() -> Strategy {
+        let mutex_conf = Arc::clone(&CONFIG);
+        let config = &mutex_conf.lock().await;
+        config.strategy.clone()
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.interval == 0 {
+            errors.push("strategy.interval 必须大于0".to_string());
+        }
+        if self.chunk_size == 0 {
+            errors.push("strategy.chunk_size 必须大于0".to_string());
+        }
+        if self.deploying_timeout <= 0 || self.report_timeout <= 0 {
+            errors.push("strategy.deploying_timeout/report_timeout 必须大于0".to_string());
+        }
+        for (key, regex) in [
+            ("gpu_regex", &self.market.gpu_regex),
+            ("cpu_regex", &self.market.cpu_regex),
+        ] {
+            if let Err(e) = regex::Regex::new(regex) {
+                errors.push(format!("strategy.market.{} 正则错误:{}", key, e));
+            }
+        }
+        if self.market.card_types.is_empty() {
+            errors.push("strategy.market.card_types 不能为空".to_string());
+        }
+        for card_type in self.market.card_types.iter() {
+            if !self.max_price.contains_key(&card_type.to_string()) {
+                errors.push(format!("strategy.max_price 未配置 {} 的价格", card_type));
+            }
+        }
+        for (card_type, price) in self.max_price.iter() {
+            if card_type.parse::<CardType>().is_err() {
+                errors.push(format!("strategy.max_price 未知显卡型号:{}", card_type));
+            }
+            if *price < 0f64 {
+                errors.push(format!("strategy.max_price.{} 不能为负数", card_type));
+            }
+        }
+        if self.hashrate.low <= 0f32 || self.hashrate.low >= self.hashrate.verify {
+            errors.push("strategy.hashrate 需满足 0 < low < verify".to_string());
+        }
+        errors
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Config {
     pub address: Address,
     pub monitor: Monitor,
     pub server: Server,
     pub clore: Clore,
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub snapshot: Snapshot,
+}
+
+/// 配置快照,每次改写配置文件前保存
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    /// 保留的快照数量
+    pub keep: usize,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Snapshot { keep: 20 }
+    }
+}
+
+/// 配置来源
+///
+/// 加载顺序(后者覆盖前者):
+/// 1. 配置文件,`--config` 指定,否则依次查找当前目录、程序所在目录下的 `.conf.toml`
+/// 2. profile 配置文件,如 `--profile dev` 对应同目录下的 `.conf.dev.toml`
+/// 3. 敏感配置文件,`--secrets` 指定,否则读取同目录下的 `.secrets.toml`(可不存在),权限须为 0600
+/// 4. 环境变量,`MONITOR__<段>__<字段>`,如 `MONITOR__CLORE__API_TOKEN`,
+///    地址列表用英文逗号分隔
+#[derive(Clone, Debug, Default, PartialEq, clap::Args)]
+pub struct ConfigSource {
+    /// 配置文件路径,默认读取 .conf.toml
+    #[arg(long = "config", global = true)]
+    pub path: Option<PathBuf>,
+    /// 配置 profile,如 dev/prod
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// 敏感配置文件路径,默认读取 .secrets.toml
+    #[arg(long, global = true)]
+    pub secrets: Option<PathBuf>,
+}
+
+impl ConfigSource {
+    /// 从环境变量 `MONITOR_CONFIG`、`MONITOR_PROFILE`、`MONITOR_SECRETS` 读取配置来源
+    pub fn from_env() -> ConfigSource {
+        ConfigSource {
+            path: std::env::var("MONITOR_CONFIG").ok().map(PathBuf::from),
+            profile: std::env::var("MONITOR_PROFILE").ok(),
+            secrets: std::env::var("MONITOR_SECRETS").ok().map(PathBuf::from),
+        }
+    }
+
+    /// 命令行未指定的项用环境变量补齐
+    pub fn or_env(self) -> ConfigSource {
+        let env = ConfigSource::from_env();
+        ConfigSource {
+            path: self.path.or(env.path),
+            profile: self.profile.or(env.profile),
+            secrets: self.secrets.or(env.secrets),
+        }
+    }
+
+    /// 当前进程使用的配置来源
+    pub fn current() -> ConfigSource {
+        CONFIG_SOURCE
+            .lock()
+            .map(|source| source.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn config_file(&self) -> Result<PathBuf, String> {
+        if let Some(path) = &self.path {
+            if path.is_file() {
+                return Ok(path.clone());
+            }
+            return Err(format!("配置文件不存在:{}", path.display()));
+        }
+        let mut dirs = Vec::new();
+        if let Ok(dir) = std::env::current_dir() {
+            dirs.push(dir);
+        }
+        if let Some(dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            dirs.push(dir);
+        }
+        dirs.iter()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+            .ok_or(format!(
+                "未找到配置文件{},已查找目录:{:?},可通过 --config 或环境变量 MONITOR_CONFIG 指定",
+                CONFIG_FILE, dirs
+            ))
+    }
+
+    /// profile 配置文件,与主配置文件同目录:`.conf.toml` -> `.conf.dev.toml`
+    pub fn profile_file(&self, config_file: &Path) -> Option<PathBuf> {
+        let profile = self.profile.as_ref()?;
+        let stem = config_file.file_stem()?.to_string_lossy();
+        let filename = match config_file.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, profile, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, profile),
+        };
+        Some(config_file.with_file_name(filename))
+    }
+
+    /// 敏感配置文件,未通过 `--secrets` 指定时为配置文件同目录下的 `.secrets.toml`
+    pub fn secrets_file(&self, config_file: &Path) -> PathBuf {
+        match &self.secrets {
+            Some(path) => path.clone(),
+            None => config_file.with_file_name(SECRETS_FILE),
+        }
+    }
+
+    /// 配置文件、profile 文件及敏感配置文件,用于监听文件变化
+    pub fn watch_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        if let Ok(config_file) = self.config_file() {
+            files.push(config_file.clone());
+            if let Some(profile_file) = self.profile_file(&config_file) {
+                files.push(profile_file);
+            }
+            files.push(self.secrets_file(&config_file));
+        }
+        files
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.watch_files()
+            .iter()
+            .map(|file| file.metadata().and_then(|meta| meta.modified()).ok())
+            .collect()
+    }
 }
 
 impl Config {
+    /// `CONFIG` 的初始值,加载失败时只记录错误并使用空配置,
+    /// 程序启动时由 `init` 加载并返回错误
     pub fn new() -> Config {
-        let result = Config::import_config();
-        assert_eq!(
-            any::TypeId::of::<Result<Config, String>>(),
-            result.type_id()
-        );
-        result.unwrap()
+        match Config::import_config() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("配置加载失败,使用空配置:{}", e);
+                Config::default()
+            }
+        }
+    }
+
+    /// 设置配置来源并立即加载,在访问 `CONFIG` 之前调用,加载失败时返回错误信息
+    pub async fn init(source: ConfigSource) -> Result<(), String> {
+        let config = Config::load(&source, None)?;
+        if let Ok(mut current) = CONFIG_SOURCE.lock() {
+            *current = source;
+        }
+        let mutex_conf = Arc::clone(&CONFIG);
+        let mut locked = mutex_conf.lock().await;
+        *locked = config;
+        Ok(())
     }
 
     pub fn import_config() -> Result<Config, String> {
-        let result = std::env::current_dir();
-        match result {
-            Ok(path) => {
-                let config_flie = path.join(".conf.toml");
-                let flie = std::fs::File::open(config_flie).map_err(|e| e.to_string())?;
-                let mut buf = String::new();
-                let mut reader = BufReader::new(flie);
-                let _ = reader.read_to_string(&mut buf);
+        Config::load(&ConfigSource::current(), None)
+    }
 
-                let config = toml::from_str::<Config>(&buf).map_err(|e| e.to_string())?;
+    /// 按 配置文件 -> profile 文件 -> 环境变量 的顺序叠加加载配置并校验,
+    /// `env` 为 None 时读取进程环境变量
+    pub fn load(
+        source: &ConfigSource,
+        env: Option<std::collections::HashMap<String, String>>,
+    ) -> Result<Config, String> {
+        let config_file = source.config_file()?;
+        let config = Config::build(source, env)?;
+        config
+            .validate()
+            .map_err(|e| format!("{}: {}", config_file.display(), e))?;
 
-                info!("{:?}", config);
+        info!("已加载配置文件:{}", config_file.display());
+        info!("{:?}", config);
+        Ok(config)
+    }
 
-                // info!("{:?}",builder);
-                Ok(config)
+    /// 叠加各来源的配置,不做内容校验
+    fn build(
+        source: &ConfigSource,
+        env: Option<std::collections::HashMap<String, String>>,
+    ) -> Result<Config, String> {
+        let config_file = source.config_file()?;
+        let mut builder = config::Config::builder()
+            .add_source(File::from(config_file.as_path()).format(FileFormat::Toml));
+        if let Some(profile_file) = source.profile_file(&config_file) {
+            if !profile_file.is_file() {
+                return Err(format!("profile配置文件不存在:{}", profile_file.display()));
             }
-            Err(ref e) => {
-                error!("{:?}", e.to_string());
-                Err(e.to_string())
+            builder =
+                builder.add_source(File::from(profile_file.as_path()).format(FileFormat::Toml));
+        }
+        let secrets_file = source.secrets_file(&config_file);
+        if secrets_file.is_file() {
+            secret::check_permission(&secrets_file)?;
+            builder =
+                builder.add_source(File::from(secrets_file.as_path()).format(FileFormat::Toml));
+        } else if source.secrets.is_some() {
+            return Err(format!("敏感配置文件不存在:{}", secrets_file.display()));
+        }
+        let environment = Environment::with_prefix(ENV_PREFIX)
+            .prefix_separator("__")
+            .separator("__")
+            .try_parsing(true)
+            .list_separator(",")
+            .with_list_parse_key("address.mst_address")
+            .with_list_parse_key("address.sub_address")
+            .source(env);
+
+        let mut config = builder
+            .add_source(environment)
+            .build()
+            .and_then(|config| config.try_deserialize::<Config>())
+            .map_err(|e| format!("{}: {}", config_file.display(), e))?;
+        // config 读取文件时会将键名转为小写,显卡型号统一转回大写
+        config.strategy.max_price = config
+            .strategy
+            .max_price
+            .into_iter()
+            .map(|(card_type, price)| (card_type.to_uppercase(), price))
+            .collect();
+        Ok(config)
+    }
+
+    /// 检查配置文件,返回全部问题,用于 `monitor config check`
+    pub fn check(source: &ConfigSource) -> Vec<String> {
+        let config_file = match source.config_file() {
+            Ok(config_file) => config_file,
+            Err(e) => return vec![e],
+        };
+        let mut problems = Vec::new();
+        let mut raws = Vec::new();
+        for file in source.watch_files().iter().filter(|file| file.is_file()) {
+            match std::fs::read_to_string(file)
+                .map_err(|e| e.to_string())
+                .and_then(|text| text.parse::<toml::Value>().map_err(|e| e.to_string()))
+            {
+                Ok(raw) => raws.push((file.clone(), raw)),
+                Err(e) => problems.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+        if let Some(profile_file) = source.profile_file(&config_file) {
+            if !profile_file.is_file() {
+                problems.push(format!("profile配置文件不存在:{}", profile_file.display()));
             }
         }
+        if !problems.is_empty() {
+            return problems;
+        }
+        match Config::build(source, None) {
+            Ok(config) => {
+                if let Ok(known) = toml::Value::try_from(&config) {
+                    for (file, raw) in raws.iter() {
+                        for key in check::unknown_keys("", raw, &known) {
+                            problems.push(format!("{}: 未知配置项 {}", file.display(), key));
+                        }
+                    }
+                }
+                problems.extend(config.problems());
+            }
+            Err(e) => problems.push(e),
+        }
+        problems
+    }
+
+    /// 校验配置内容
+    pub fn validate(&self) -> Result<(), String> {
+        let errors = self.problems();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// 配置内容的全部问题
+    pub fn problems(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let address = &self.address;
+        for (key, list) in [
+            ("mst_address", &address.mst_address),
+            ("sub_address", &address.sub_address),
+        ] {
+            for (index, addr) in list.iter().enumerate() {
+                if !check::is_nimble_address(addr) {
+                    errors.push(format!("address.{} 不是有效的nimble地址:{:?}", key, addr));
+                }
+                if list[..index].contains(addr) {
+                    errors.push(format!("address.{} 地址重复:{}", key, addr));
+                }
+            }
+        }
+        for addr in address.mst_address.iter() {
+            if address.sub_address.contains(addr) {
+                errors.push(format!("地址同时出现在mst_address和sub_address:{}", addr));
+            }
+        }
+        for (key, url, base) in [
+            ("clore.api_host", &self.clore.api_host, true),
+            ("clore.web_api_host", &self.clore.web_api_host, true),
+            (
+                "monitor.api_report_log",
+                &self.monitor.api_report_log,
+                false,
+            ),
+            (
+                "monitor.heartbeat.api",
+                &self.monitor.heartbeat_url(),
+                false,
+            ),
+        ] {
+            errors.extend(check::check_url(key, url, base));
+        }
+        if self.monitor.heartbeat.interval == 0 {
+            errors.push("monitor.heartbeat.interval 必须大于0".to_string());
+        }
+        if self
+            .monitor
+            .stall_after
+            .is_some_and(|stall_after| stall_after <= 0)
+        {
+            errors.push("monitor.stall_after 必须大于0".to_string());
+        }
+        if self
+            .monitor
+            .install_stall_after
+            .is_some_and(|install_stall_after| install_stall_after <= 0)
+        {
+            errors.push("monitor.install_stall_after 必须大于0".to_string());
+        }
+        if self.monitor.host.interval == 0 {
+            errors.push("monitor.host.interval 必须大于0".to_string());
+        }
+        if self.monitor.telemetry.interval == 0 {
+            errors.push("monitor.telemetry.interval 必须大于0".to_string());
+        }
+        if self.monitor.telemetry.samples == 0 {
+            errors.push("monitor.telemetry.samples 必须大于0".to_string());
+        }
+        if self.monitor.gpu.backend == GpuBackendKind::Fixture {
+            match &self.monitor.gpu.fixture {
+                Some(fixture) if !fixture.exists() => errors.push(format!(
+                    "monitor.gpu.fixture 文件不存在:{}",
+                    fixture.display()
+                )),
+                Some(_) => {}
+                None => errors.push(
+                    "monitor.gpu.backend 为 fixture 时需配置 monitor.gpu.fixture".to_string(),
+                ),
+            }
+        }
+        for account in self.clore.get_accounts() {
+            let command = account.command.clone().unwrap_or_default();
+            for name in check::unknown_placeholders(&command) {
+                errors.push(format!(
+                    "账户{}的command 含未知占位符{{{}}},可用:{:?}",
+                    account.name,
+                    name,
+                    check::PLACEHOLDERS
+                ));
+            }
+            let secrets = [
+                ("api_token", &account.api_token),
+                ("web_token", &account.web_token),
+                ("ssh_passwd", &account.ssh_passwd),
+            ];
+            for (key, value) in secrets.iter() {
+                if value.expose().is_empty() {
+                    errors.push(format!(
+                        "账户{}的clore.{} 未配置,可写入配置文件、{} 或环境变量",
+                        account.name, key, SECRETS_FILE
+                    ));
+                }
+            }
+        }
+        let mut names: Vec<&String> = Vec::new();
+        let mut assigned: Vec<&String> = Vec::new();
+        for account in self.clore.accounts.iter() {
+            if account.name.is_empty() || names.contains(&&account.name) {
+                errors.push(format!(
+                    "clore.accounts 账户名为空或重复:{:?}",
+                    account.name
+                ));
+            }
+            names.push(&account.name);
+            for addr in account.addresses.iter() {
+                if !self.address.sub_address.contains(addr) {
+                    errors.push(format!(
+                        "账户{}的地址不在sub_address中:{}",
+                        account.name, addr
+                    ));
+                }
+                if assigned.contains(&addr) {
+                    errors.push(format!("地址被分配给多个账户:{}", addr));
+                }
+                assigned.push(addr);
+            }
+        }
+        errors.extend(self.strategy.validate());
+        errors
     }
 
-    pub fn export_config(any: impl Serialize) -> Result<(), String> {
-        let result = std::env::current_dir();
-        match result {
-            Ok(path) => {
-                let config_flie = path.join(".conf.bak.toml");
-                let file = OpenOptions::new()
-                    .create(true)
-                    .read(true)
-                    .write(true)
-                    .open(config_flie)
-                    .map_err(|e| e.to_string())?;
+    /// 字段级配置差异,敏感字段以 ****** 代替
+    pub fn diff(&self, other: &Config) -> Vec<String> {
+        match (toml::Value::try_from(self), toml::Value::try_from(other)) {
+            (Ok(old), Ok(new)) => diff_values(&old, &new),
+            _ => Vec::new(),
+        }
+    }
 
-                let mut writer: BufWriter<_> = BufWriter::new(file);
-                let string = toml::to_string(&any).unwrap();
-                let _ = writer.write_all(&string.as_bytes());
-                Ok(())
+    /// 监听配置文件变化,校验通过后替换 `CONFIG`
+    pub async fn watch() {
+        let source = ConfigSource::current();
+        let mut modified = source.modified();
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            let current = source.modified();
+            if current == modified {
+                continue;
             }
-            Err(ref e) => {
-                error!("{:?}", e.to_string());
-                Err(e.to_string())
+            modified = current;
+            info!("检测到配置文件变化:{:?}", source.watch_files());
+            let config = match Config::load(&source, None) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("新配置校验失败,继续使用旧配置:{}", e);
+                    continue;
+                }
+            };
+            let mutex_conf = Arc::clone(&CONFIG);
+            let mut locked = mutex_conf.lock().await;
+            let changes = locked.diff(&config);
+            if changes.is_empty() {
+                info!("配置内容无变化");
+                continue;
             }
+            *locked = config;
+            drop(locked);
+            warn!("配置已重新加载:\n{}", changes.join("\n"));
         }
     }
 }
+
+/// 逐项对比两份配置,敏感字段只提示变化不输出值
+fn diff_values(old: &toml::Value, new: &toml::Value) -> Vec<String> {
+    let (mut old_fields, mut new_fields) = (Vec::new(), Vec::new());
+    flatten("", old, &mut old_fields);
+    flatten("", new, &mut new_fields);
+    let (old, new) = (old_fields, new_fields);
+    let mut changes = Vec::new();
+    for (key, value) in old.iter() {
+        let masked = is_secret(key);
+        match new.iter().find(|(new_key, _)| new_key == key) {
+            Some((_, new_value)) if new_value == value => {}
+            Some((_, new_value)) => match (value, new_value) {
+                (toml::Value::Array(value), toml::Value::Array(new_value)) => {
+                    let mut items = Vec::new();
+                    // 数组中的表(如 clore.accounts)可能含敏感字段,逐项隐藏后输出
+                    for item in new_value.iter().filter(|item| !value.contains(item)) {
+                        items.push(format!("+{}", redact(item)));
+                    }
+                    for item in value.iter().filter(|item| !new_value.contains(item)) {
+                        items.push(format!("-{}", redact(item)));
+                    }
+                    if masked {
+                        changes.push(format!("{}: ****** -> ******", key));
+                    } else {
+                        changes.push(format!("{}: {}", key, items.join(" ")));
+                    }
+                }
+                _ if masked => changes.push(format!("{}: ****** -> ******", key)),
+                _ => changes.push(format!(
+                    "{}: {} -> {}",
+                    key,
+                    redact(value),
+                    redact(new_value)
+                )),
+            },
+            None => changes.push(format!("{}: 已删除", key)),
+        }
+    }
+    for (key, value) in new.iter() {
+        if !old.iter().any(|(old_key, _)| old_key == key) {
+            if is_secret(key) {
+                changes.push(format!("{}: 新增 ******", key));
+            } else {
+                changes.push(format!("{}: 新增 {}", key, redact(value)));
+            }
+        }
+    }
+    changes
+}
+
+fn flatten(prefix: &str, value: &toml::Value, fields: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, fields);
+            }
+        }
+        _ => fields.push((prefix.to_string(), value.clone())),
+    }
+}
+
+/// 隐藏值中各层表的敏感字段,用于输出数组中的表
+fn redact(value: &toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .iter()
+                .map(|(key, value)| {
+                    if is_secret(key) {
+                        (key.clone(), toml::Value::String(secret::MASK.to_string()))
+                    } else {
+                        (key.clone(), redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        toml::Value::Array(array) => toml::Value::Array(array.iter().map(redact).collect()),
+        _ => value.clone(),
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let field = key.rsplit('.').next().unwrap_or(key);
+    secret::SECRET_FIELDS.contains(&field)
+}
@@ -0,0 +1,92 @@
+use regex::Regex;
+
+/// 下单启动命令中支持的占位符
+pub const PLACEHOLDERS: [&str; 3] = ["server_id", "card_number", "address"];
+
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut chk: u32 = 1;
+    for value in values.iter() {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ (*value as u32);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+/// 校验 nimble 开头的 bech32 地址(含校验和)
+pub fn is_nimble_address(address: &str) -> bool {
+    if address.to_lowercase() != address {
+        return false;
+    }
+    let (hrp, data) = match address.rsplit_once('1') {
+        Some(split) => split,
+        None => return false,
+    };
+    if hrp != "nimble" || data.len() < 6 {
+        return false;
+    }
+    let mut values = hrp.bytes().map(|c| c >> 5).collect::<Vec<u8>>();
+    values.push(0);
+    values.extend(hrp.bytes().map(|c| c & 31));
+    for c in data.chars() {
+        match CHARSET.find(c) {
+            Some(index) => values.push(index as u8),
+            None => return false,
+        }
+    }
+    polymod(&values) == 1
+}
+
+/// 命令模板中未知的占位符,`${VAR}` 形式的shell变量不计入
+pub fn unknown_placeholders(command: &str) -> Vec<String> {
+    let regex = Regex::new(r"(\$?)\{([^{}\s]*)\}").unwrap();
+    regex
+        .captures_iter(command)
+        .map(|captures| captures.extract::<2>().1)
+        .filter(|[dollar, name]| dollar.is_empty() && !PLACEHOLDERS.contains(name))
+        .map(|[_, name]| name.to_string())
+        .collect()
+}
+
+/// 配置文件中存在但 `Config` 中没有的键,通常是拼写错误
+pub fn unknown_keys(prefix: &str, raw: &toml::Value, known: &toml::Value) -> Vec<String> {
+    let mut keys = Vec::new();
+    match (raw, known) {
+        (toml::Value::Table(raw), toml::Value::Table(known)) => {
+            for (key, value) in raw.iter() {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match known.get(key) {
+                    Some(known) => keys.extend(unknown_keys(&path, value, known)),
+                    None => keys.push(path),
+                }
+            }
+        }
+        (toml::Value::Array(raw), toml::Value::Array(known)) => {
+            for (index, (raw, known)) in raw.iter().zip(known.iter()).enumerate() {
+                keys.extend(unknown_keys(&format!("{}[{}]", prefix, index), raw, known));
+            }
+        }
+        _ => {}
+    }
+    keys
+}
+
+/// url格式校验,`base` 为 true 时要求以 / 结尾(代码中直接拼接路径)
+pub fn check_url(key: &str, url: &str, base: bool) -> Option<String> {
+    match reqwest::Url::parse(url) {
+        Ok(_) if base && !url.ends_with('/') => Some(format!("{} 需以/结尾:{}", key, url)),
+        Ok(_) => None,
+        Err(e) => Some(format!("{} 不是有效的url:{:?},{}", key, url, e)),
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::{Debug, Display};
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// 打印时的占位内容
+pub const MASK: &str = "******";
+
+/// 日志中需要隐藏值的字段名
+pub const SECRET_FIELDS: [&str; 10] = [
+    "api_token",
+    "web_token",
+    "ssh_passwd",
+    "ssh_password",
+    "token",
+    "auth",
+    "SSH_PASSWORD",
+    "WEBUI_PASSWORD",
+    "secret",
+    "MONITOR__MONITOR__HEARTBEAT__SECRET",
+];
+
+/// 敏感信息,`Debug`/`Display` 只输出 `******`
+///
+/// 序列化时输出原值,以便写回配置文件或提交给 clore 接口,
+/// 需要打印请求体时使用 [`redact`]
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl<T> Display for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(MASK)
+    }
+}
+
+impl<T: Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// 序列化为json,并隐藏敏感字段的值,用于打印请求体
+pub fn redact(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(mut value) => {
+            mask(&mut value);
+            value.to_string()
+        }
+        Err(e) => format!("序列化失败:{}", e),
+    }
+}
+
+fn mask(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *value = serde_json::Value::String(MASK.to_string());
+                } else {
+                    mask(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(mask),
+        _ => {}
+    }
+}
+
+/// 敏感配置文件只允许当前用户读写(0600)
+pub fn check_permission(path: &Path) -> Result<(), String> {
+    let metadata = path
+        .metadata()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(format!(
+                "{}: 权限过大({:o}),请执行 chmod 600 {}",
+                path.display(),
+                mode,
+                path.display()
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use tracing::info;
+
+/// 快照目录名,位于配置文件同目录下
+pub const SNAPSHOT_DIR: &str = ".conf.snapshots";
+
+/// 配置文件快照,文件名为 `{配置文件名}.{时间戳}.toml`,按名称排序即按时间排序
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub fn snapshot_dir(config_file: &Path) -> PathBuf {
+    config_file.with_file_name(SNAPSHOT_DIR)
+}
+
+fn stem(config_file: &Path) -> String {
+    config_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// 先写临时文件再重命名,避免写到一半或旧内容残留
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or(format!("无效的文件路径:{}", path.display()))?;
+    let tmp = path.with_file_name(format!("{}.tmp", filename));
+    // 内容可能含敏感字段,新文件仅本用户可读写,已有文件沿用原权限
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp)
+        .map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    let result = file
+        .write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .and_then(|_| match path.metadata() {
+            Ok(metadata) => fs::set_permissions(&tmp, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("{}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+/// 已有快照,从旧到新
+pub fn list(config_file: &Path) -> Result<Vec<Entry>, String> {
+    let dir = snapshot_dir(config_file);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}.", stem(config_file));
+    let mut entries = fs::read_dir(&dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().to_string();
+            if name.starts_with(&prefix) && name.ends_with(".toml") {
+                Some(Entry { name, path })
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<Entry>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// 按文件名或时间戳查找快照
+pub fn find(config_file: &Path, name: &str) -> Result<Entry, String> {
+    let stem = stem(config_file);
+    list(config_file)?
+        .into_iter()
+        .find(|entry| entry.name == name || entry.name == format!("{}.{}.toml", stem, name))
+        .ok_or(format!("快照不存在:{}", name))
+}
+
+/// 保存配置文件当前内容为快照并清理超出 `keep` 的旧快照,
+/// 与最新快照内容相同时不重复保存
+pub fn save(config_file: &Path, keep: usize) -> Result<Option<Entry>, String> {
+    if !config_file.is_file() {
+        return Ok(None);
+    }
+    let content =
+        fs::read_to_string(config_file).map_err(|e| format!("{}: {}", config_file.display(), e))?;
+    let entries = list(config_file)?;
+    if let Some(latest) = entries.last() {
+        if fs::read_to_string(&latest.path).ok().as_ref() == Some(&content) {
+            return Ok(None);
+        }
+    }
+
+    let dir = snapshot_dir(config_file);
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let timestamp = Local::now().format("%Y%m%d%H%M%S%3f");
+    let mut name = format!("{}.{}.toml", stem(config_file), timestamp);
+    let mut index = 1;
+    while dir.join(&name).exists() {
+        name = format!("{}.{}-{}.toml", stem(config_file), timestamp, index);
+        index += 1;
+    }
+    let path = dir.join(&name);
+    write_atomic(&path, &content)?;
+    info!("已保存配置快照:{}", path.display());
+    prune(config_file, keep)?;
+    Ok(Some(Entry { name, path }))
+}
+
+/// 只保留最新的 `keep` 个快照
+pub fn prune(config_file: &Path, keep: usize) -> Result<(), String> {
+    let entries = list(config_file)?;
+    if entries.len() <= keep {
+        return Ok(());
+    }
+    for entry in entries[..entries.len() - keep].iter() {
+        fs::remove_file(&entry.path).map_err(|e| format!("{}: {}", entry.path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn read_toml(path: &Path) -> Result<toml::Value, String> {
+    fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| text.parse::<toml::Value>().map_err(|e| e.to_string()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// 快照到当前配置文件的变化
+pub fn diff(config_file: &Path, name: &str) -> Result<Vec<String>, String> {
+    let entry = find(config_file, name)?;
+    Ok(super::diff_values(
+        &read_toml(&entry.path)?,
+        &read_toml(config_file)?,
+    ))
+}
+
+/// 回滚到指定快照,回滚前先保存当前配置,返回配置的变化
+pub fn rollback(config_file: &Path, name: &str, keep: usize) -> Result<Vec<String>, String> {
+    let entry = find(config_file, name)?;
+    let snapshot = read_toml(&entry.path)?;
+    let changes = super::diff_values(&read_toml(config_file)?, &snapshot);
+    let content =
+        fs::read_to_string(&entry.path).map_err(|e| format!("{}: {}", entry.path.display(), e))?;
+    save(config_file, keep)?;
+    write_atomic(config_file, &content)?;
+    info!("配置已回滚到快照:{}", entry.name);
+    Ok(changes)
+}
@@ -2,3 +2,4 @@ pub mod config;
 pub mod log;
 pub mod monitor;
 pub mod server;
+pub mod shutdown;
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::SeekFrom;
 use std::ops::{Deref, DerefMut};
@@ -13,23 +14,39 @@ use strum::Display;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt};
 use tokio::io::{AsyncSeekExt, BufReader};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tracing::{error, info, warn};
 
+use crate::config::Strategy;
+use crate::monitor::lifecycle::MinerEvent;
 use crate::monitor::Monitor;
+use crate::shutdown;
 
 lazy_static! {
-    pub static ref LOG_CHANNEL: Arc<Mutex<(UnboundedSender<Massage>, UnboundedReceiver<Massage>)>> =
-        Arc::new(Mutex::new(unbounded_channel::<Massage>()));
+    /// 日志分析发给 `monitor()` 的消息,发送端无需加锁,接收端由消息处理任务独占
+    pub static ref LOG_CHANNEL: (UnboundedSender<Massage>, Arc<Mutex<UnboundedReceiver<Massage>>>) = {
+        let (sender, receiver) = unbounded_channel::<Massage>();
+        (sender, Arc::new(Mutex::new(receiver)))
+    };
     pub static ref LOG_FILES: Arc<Mutex<Logs>> = Arc::new(Mutex::new(Logs::new()));
+    /// 后台进行中的上报,退出前等待完成
+    static ref UPLOADS: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(Vec::new()));
+    /// 退出时各日志读取任务已输出剩余日志并发出阶段事件
+    static ref TAILERS_STOPPED: watch::Sender<bool> = watch::channel(false).0;
 }
 
+/// 退出时等待日志读取任务输出剩余日志的最长时间
+pub const TAILER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[derive(Debug, Clone)]
 pub enum MsgType {
     NORMAL,
     RESTART,
     REPORT,
+    /// 挖矿进程阶段事件,驱动 `Lifecycle` 状态机
+    EVENT(MinerEvent),
 }
 
 #[derive(Debug, Clone)]
@@ -39,6 +56,44 @@ pub struct Massage {
     pub body: String,
 }
 
+/// 合并发送挖矿事件:阶段变化时立即发送,同一阶段随日志汇总定时发送最新一条
+struct EventSender {
+    address: String,
+    pending: Option<MinerEvent>,
+    sent: Option<MinerEvent>,
+}
+
+impl EventSender {
+    fn new(address: &str) -> EventSender {
+        EventSender {
+            address: address.to_string(),
+            pending: None,
+            sent: None,
+        }
+    }
+
+    async fn push(&mut self, event: MinerEvent) {
+        let changed = self
+            .sent
+            .as_ref()
+            .map(|sent| std::mem::discriminant(sent) != std::mem::discriminant(&event))
+            .unwrap_or(true);
+        if changed {
+            self.pending = Some(event);
+            self.flush().await;
+        } else {
+            self.pending = Some(event);
+        }
+    }
+
+    async fn flush(&mut self) {
+        if let Some(event) = self.pending.take() {
+            Logs::send(&self.address, MsgType::EVENT(event.clone()), "").await;
+            self.sent = Some(event);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Log {
     pub filename: PathBuf,
@@ -64,6 +119,15 @@ impl Logs {
         logs
     }
 
+    /// 发送消息给 `monitor()` 主循环
+    pub async fn send(address: &str, msg_type: MsgType, body: &str) {
+        let _ = LOG_CHANNEL.0.send(Massage {
+            address: address.to_string(),
+            msg_type,
+            body: body.to_string(),
+        });
+    }
+
     pub async fn upload(mesage: Massage) {
         if mesage.body.is_empty() {
             return;
@@ -81,6 +145,35 @@ impl Logs {
             error!("上报数据失败:{:?}", result);
         }
     }
+
+    /// 后台上报,不等待上报完成,退出前由 `Logs::flush` 等待
+    pub async fn spawn_upload(mesage: Massage) {
+        let uploads = Arc::clone(&UPLOADS);
+        let mut uploads_locked = uploads.lock().await;
+        uploads_locked.retain(|upload| !upload.is_finished());
+        uploads_locked.push(tokio::spawn(Logs::upload(mesage)));
+    }
+
+    /// 等待后台上报完成,超时后放弃剩余上报
+    pub async fn flush(timeout: std::time::Duration) {
+        let uploads = {
+            let uploads = Arc::clone(&UPLOADS);
+            let mut uploads_locked = uploads.lock().await;
+            std::mem::take(&mut *uploads_locked)
+        };
+        let pending = uploads
+            .iter()
+            .filter(|upload| !upload.is_finished())
+            .count();
+        if pending == 0 {
+            return;
+        }
+        info!("等待{}条数据上报完成", pending);
+        let result = tokio::time::timeout(timeout, futures::future::join_all(uploads)).await;
+        if result.is_err() {
+            warn!("数据上报超时,放弃剩余上报");
+        }
+    }
 }
 
 impl Deref for Logs {
@@ -194,10 +287,13 @@ impl Logs {
                     "{} \n{} \n总任务数:{},成功任务数:{},失败任务数:{},合计奖励:{} $NIM,单地址合集奖励:{:.3} $NIM", 
                     address, s.trim(),total_task,total_task_succss,total_task-total_task_succss,total,total_task_succss/cards);
                 let _ = reader.seek(SeekFrom::Start(0)).await;
-                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                if shutdown::sleep(std::time::Duration::from_secs(60)).await {
+                    return;
+                }
             }
         }
         let mut hashstring = IndexMap::<String, String>::new();
+        let mut hashrate = Strategy::get_config().await.hashrate;
 
         //拉取任务失败
         let request_task = Regex::new(r"Failed to init particle").unwrap();
@@ -217,17 +313,26 @@ impl Logs {
         let verify = Regex::new(r"\{'(loss|eval_loss).*}").unwrap();
 
         // 任务完成时输出
-        let _complated = Regex::new(r"completed the task.*").unwrap();
+        let complated = Regex::new(r"completed the task.*").unwrap();
+        let mut events = EventSender::new(&address);
 
         let mut instant = tokio::time::Instant::now();
         let mut lines = reader.lines();
-        while let Ok(some_line) = lines.next_line().await {
+        loop {
+            let some_line = tokio::select! {
+                line = lines.next_line() => line,
+                _ = shutdown::wait() => break,
+            };
+            let Ok(some_line) = some_line else {
+                break;
+            };
             if let Some(line) = some_line {
                 let line = line.trim().to_string();
                 if line.is_empty() {
                     continue;
                 }
                 if verify.captures(&line).is_some() {
+                    events.push(MinerEvent::Verifying).await;
                     continue;
                 }
                 let complex = complex_regex.captures(&line);
@@ -240,6 +345,14 @@ impl Logs {
                         address, operate, extra, percent, prce, total, downspeed
                     );
                     hashstring.insert(format!("{}{}", operate, extra), string);
+                    // 带上完成数,百分比不变时也能判断是否有进展
+                    let percent = format!("{} {}/{}", percent, prce, total);
+                    let new = if operate == "Downloading" {
+                        MinerEvent::Downloading { percent }
+                    } else {
+                        MinerEvent::Preparing { percent }
+                    };
+                    events.push(new).await;
                     continue;
                 }
 
@@ -251,20 +364,28 @@ impl Logs {
                     #[allow(unused_assignments)]
                     let mut string = String::new();
                     match it {
-                        it if it > 20f32 => {
+                        it if it > hashrate.verify => {
                             // 验算时，这个算力的值非常大，不应该算进到日志里面去
                             string = format!(
                                 "{} 算力核算 完成百分比:{:<3} 完成进度:{:<5}/{:<5} 当前算力:{}it",
                                 address, percent, prce, total, it
                             );
                             hashstring.insert("verify_it".to_string(), string);
+                            events.push(MinerEvent::Verifying).await;
                         }
-                        it if it < 11f32 => {
+                        it if it < hashrate.low => {
                             string = format!(
                                 "{} 异常算力 完成百分比:{:<3} 完成进度:{:<5}/{:<5} 当前算力:{}it",
                                 address, percent, prce, total, it
                             );
                             hashstring.insert("need_restart".to_string(), string);
+                            let percent = format!("{} {}/{}", percent, prce, total);
+                            events
+                                .push(MinerEvent::Progress {
+                                    percent,
+                                    hashrate: it,
+                                })
+                                .await;
                         }
                         _ => {
                             // 正常范围算力
@@ -274,6 +395,13 @@ impl Logs {
                             );
 
                             hashstring.insert("work_it".to_string(), string);
+                            let percent = format!("{} {}/{}", percent, prce, total);
+                            events
+                                .push(MinerEvent::Progress {
+                                    percent,
+                                    hashrate: it,
+                                })
+                                .await;
                         }
                     }
 
@@ -281,59 +409,106 @@ impl Logs {
                 }
                 if request_task.captures(&line).is_some() {
                     hashstring.insert("need_restart".to_string(), line.clone());
+                    events.push(MinerEvent::Failed(line.clone())).await;
                     continue;
                 }
+                if complated.is_match(&line) {
+                    events.push(MinerEvent::Completed).await;
+                }
 
                 let string = format!("{} {}", address, line);
                 hashstring.insert(line.to_string(), string);
             }
 
             if !hashstring.is_empty() && instant.elapsed() > tokio::time::Duration::from_secs(5) {
-                let body = hashstring
-                    .iter()
-                    .map(|(_, value)| value.clone())
-                    .collect::<Vec<String>>()
-                    .join("\n");
-                let digest = format!("{:?}", md5::compute(body.as_bytes()));
-                let split = "-".repeat(100);
-                hashstring.insert(digest, split);
-
-                println!("{}", body);
+                Logs::report(&address, &mut hashstring, &mut events, true).await;
                 instant = Instant::now();
-                if hashstring.contains_key("need_restart") {
-                    let restart = hashstring
-                        .get("need_restart")
-                        .unwrap_or(&"".to_string())
-                        .to_string();
-                    let log_channel = Arc::clone(&LOG_CHANNEL);
-                    let locked = log_channel.lock().await;
-                    let _ = (*locked).0.send(Massage {
-                        address: address.clone(),
-                        msg_type: MsgType::RESTART,
-                        body: restart.clone(),
-                    });
-                    drop(locked);
-                    warn!("需要重启:{:?}", restart);
-                }
-                hashstring.clear();
+                // 算力阈值随配置热加载更新
+                hashrate = Strategy::get_config().await.hashrate;
             }
         }
+        // 退出前输出尚未汇总的日志并发出最新的阶段事件,退出中不再请求重启
+        let restart = !shutdown::is_shutdown();
+        Logs::report(&address, &mut hashstring, &mut events, restart).await;
+    }
+
+    /// 输出汇总的日志并发出最新的阶段事件,`restart` 为 true 时发送其中的重启请求
+    async fn report(
+        address: &str,
+        hashstring: &mut IndexMap<String, String>,
+        events: &mut EventSender,
+        restart: bool,
+    ) {
+        events.flush().await;
+        if hashstring.is_empty() {
+            return;
+        }
+        let body = hashstring
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let digest = format!("{:?}", md5::compute(body.as_bytes()));
+        let split = "-".repeat(100);
+        hashstring.insert(digest, split);
+
+        println!("{}", body);
+        if let Some(need_restart) = hashstring.get("need_restart") {
+            if restart {
+                Logs::send(address, MsgType::RESTART, need_restart).await;
+                warn!("需要重启:{:?}", need_restart);
+            } else {
+                warn!("程序退出中,不再请求重启:{:?}", need_restart);
+            }
+        }
+        hashstring.clear();
+    }
+
+    /// 等待日志读取任务退出,最多等待 `timeout`
+    pub async fn wait_tailers(timeout: std::time::Duration) {
+        let mut receiver = TAILERS_STOPPED.subscribe();
+        let _ = tokio::time::timeout(timeout, receiver.wait_for(|stopped| *stopped)).await;
     }
 
     pub async fn monitor() {
+        let mut tailers = HashMap::<PathBuf, JoinHandle<()>>::new();
         loop {
             let log_files = Arc::clone(&LOG_FILES);
             let mut log_files_locked = log_files.lock().await;
             // 日志分析
             (*log_files_locked).iter_log_files().await;
             for log in (*log_files_locked).iter_mut() {
-                if !log.spawn && log.filename.exists() {
+                // 读取任务异常退出后重新启动
+                let exited = tailers
+                    .get(&log.filename)
+                    .map(|tailer| tailer.is_finished())
+                    .unwrap_or(false);
+                if exited {
+                    warn!("日志读取任务已退出,重新启动:{:?}", log.filename);
+                }
+                if (!log.spawn || exited) && log.filename.exists() {
                     log.spawn = true;
-                    tokio::spawn(Logs::read_log_file(log.clone()));
+                    let tailer = tokio::spawn(Logs::read_log_file(log.clone()));
+                    tailers.insert(log.filename.clone(), tailer);
                 }
             }
             drop(log_files_locked);
-            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            if shutdown::sleep(std::time::Duration::from_secs(10)).await {
+                // 读取任务收到退出通知后输出剩余日志,超时未退出的直接结束
+                let tailers = tailers.into_values().collect::<Vec<JoinHandle<()>>>();
+                let aborts = tailers
+                    .iter()
+                    .map(|tailer| tailer.abort_handle())
+                    .collect::<Vec<_>>();
+                let joined =
+                    tokio::time::timeout(TAILER_TIMEOUT, futures::future::join_all(tailers)).await;
+                if joined.is_err() {
+                    warn!("日志读取任务未在{}秒内退出", TAILER_TIMEOUT.as_secs());
+                    aborts.iter().for_each(|abort| abort.abort());
+                }
+                TAILERS_STOPPED.send_replace(true);
+                return;
+            }
         }
     }
 }
@@ -357,7 +532,15 @@ pub struct RunLog {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct RunLogs(Vec<RunLog>);
+pub struct RunLogs(Vec<RunLog>);
+
+impl RunLogs {
+    /// 读取挖矿程序输出的任务记录 my_logs.json
+    pub fn load(path: &std::path::Path) -> Result<RunLogs, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str::<RunLogs>(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
 
 impl Deref for RunLogs {
     type Target = Vec<RunLog>;
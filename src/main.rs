@@ -1,13 +1,120 @@
-use monitor::monitor::monitor;
+use clap::{Parser, Subcommand};
+use monitor::config::{snapshot, Config, ConfigSource, Snapshot};
+use monitor::monitor::{monitor, once};
 use time::{macros::format_description, UtcOffset};
 use tracing_subscriber::fmt::time::OffsetTime;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "nimble 挖矿监控程序", long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    source: ConfigSource,
+    /// 只检测显卡、服务、进程和地址,输出每张显卡将执行的操作及原因,不做任何改动
+    #[arg(long)]
+    dry_run: bool,
+    /// 只执行一次检查和处理后退出,退出码:0 服务正常,1 失败,2 已处理(dry-run 时为需要处理),3 配置加载失败。
+    /// native 后端需常驻运行守护挖矿进程,不支持 --once
+    #[arg(long)]
+    once: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 配置文件相关操作
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// 检查配置文件,一次列出全部问题
+    Check,
+    /// 列出配置快照
+    Snapshots,
+    /// 对比快照与当前配置
+    Diff {
+        /// 快照文件名或时间戳
+        name: String,
+    },
+    /// 回滚到指定快照
+    Rollback {
+        /// 快照文件名或时间戳
+        name: String,
+    },
+}
+
+fn config_command(source: ConfigSource, action: ConfigAction) -> Result<(), String> {
+    match action {
+        ConfigAction::Check => {
+            let problems = Config::check(&source);
+            if !problems.is_empty() {
+                for (index, problem) in problems.iter().enumerate() {
+                    println!("{}. {}", index + 1, problem);
+                }
+                return Err(format!("共{}个问题", problems.len()));
+            }
+            println!("配置检查通过");
+        }
+        ConfigAction::Snapshots => {
+            for entry in snapshot::list(&source.config_file()?)? {
+                println!("{}", entry.name);
+            }
+        }
+        ConfigAction::Diff { name } => {
+            for change in snapshot::diff(&source.config_file()?, &name)? {
+                println!("{}", change);
+            }
+        }
+        ConfigAction::Rollback { name } => {
+            // 当前配置可能已损坏,读取失败时使用默认保留数量
+            let keep = Config::load(&source, None)
+                .map(|config| config.snapshot.keep)
+                .unwrap_or(Snapshot::default().keep);
+            for change in snapshot::rollback(&source.config_file()?, &name, keep)? {
+                println!("{}", change);
+            }
+            println!("已回滚到快照:{}", name);
+        }
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let local_time = OffsetTime::new(
         UtcOffset::from_hms(8, 0, 0).unwrap(),
         format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"),
     );
-    tracing_subscriber::fmt().with_timer(local_time).init();
+    let cli = Cli::parse();
+    // dry-run 只输出错误日志,避免与检测结果混在一起
+    let level = if cli.dry_run {
+        tracing::Level::ERROR
+    } else {
+        tracing::Level::INFO
+    };
+    tracing_subscriber::fmt()
+        .with_timer(local_time)
+        .with_max_level(level)
+        .init();
+    if let Some(Command::Config { action }) = cli.command {
+        if let Err(e) = config_command(cli.source.or_env(), action) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Err(e) = Config::init(cli.source.or_env()).await {
+        eprintln!("配置加载失败:\n{}", e);
+        std::process::exit(once::Outcome::InvalidConfig as i32);
+    }
+    if cli.dry_run || cli.once {
+        let outcome = once::once(cli.dry_run).await;
+        std::process::exit(outcome as i32);
+    }
     monitor().await;
     Ok(())
 }
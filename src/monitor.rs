@@ -1,45 +1,177 @@
 use lazy_static::lazy_static;
-use pm::{Action, Process};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::process::{Command, Stdio};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tracing::{error, info, warn};
 
+use self::bootstrap::Allocation;
+use self::host::HostHealth;
+use self::lifecycle::{Lifecycle, MinerEvent, MinerState};
 use self::nvidia::GeForces;
-use crate::config::CONFIG;
-use crate::log::{self, LOG_CHANNEL};
+use self::policy::{Decision, RESTART_POLICY};
+use self::procfs::{MinerProcess, ProcScanner};
+use self::reconcile::{GpuPlan, Plan, Step};
+use self::supervisor::{Status, Supervisor};
+use self::telemetry::{GpuSamples, GpuSummary};
+use crate::config::snapshot::write_atomic;
+use crate::config::{ShutdownAction, CONFIG};
+use crate::log::{Logs, Massage, MsgType};
+use crate::shutdown;
 
+pub mod api;
+pub mod bootstrap;
+pub mod heartbeat;
+pub mod host;
+pub mod lifecycle;
+pub mod metrics;
 pub mod nvidia;
+pub mod once;
 pub mod pm;
+pub mod policy;
+pub mod procfs;
+pub mod reconcile;
+pub mod supervisor;
+pub mod task;
+pub mod telemetry;
+/// 退出时等待任务结束及数据上报的最长时间
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 lazy_static! {
     pub static ref MONITOR: Arc<Mutex<Monitor>> = Arc::new(Mutex::new(Monitor::new()));
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Monitor {
     server_id: Option<u32>,
     address: Vec<String>,
     nvidias: GeForces,
     upload_log: HashMap<String, Vec<String>>,
+    /// 各地址挖矿进程的状态
+    lifecycles: HashMap<String, Lifecycle>,
+    /// 最近一次主机检查结果
+    host: Option<HostHealth>,
+    /// 各显卡最近的运行数据采样
+    telemetry: GpuSamples,
+    /// 地址绑定的显卡UUID,保存在地址分配文件中
+    gpus: BTreeMap<String, String>,
 }
 
 impl Monitor {
     fn new() -> Monitor {
+        let address = Monitor::get_address();
+        let now = chrono::Local::now();
+        let lifecycles = address
+            .iter()
+            .map(|address| (address.clone(), Lifecycle::new(now)))
+            .collect();
+        let gpus = Allocation::load(&Allocation::path())
+            .map(|allocation| allocation.gpus)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(addr, _)| address.contains(addr))
+            .collect();
         Monitor {
             server_id: Monitor::get_server_id(),
-            address: Monitor::get_address(),
-            nvidias: GeForces::new(),
+            address,
+            nvidias: GeForces::default(),
             upload_log: HashMap::<String, Vec<String>>::new(),
+            lifecycles,
+            host: None,
+            telemetry: GpuSamples::default(),
+            gpus,
+        }
+    }
+
+    /// 地址与显卡的对应关系,已绑定的地址使用绑定的显卡
+    pub fn assignments(&self) -> Vec<reconcile::Assignment> {
+        let nvidias = self.nvidias.get_normal_nvidias();
+        reconcile::assign(&self.address, &nvidias, &self.gpus)
+    }
+
+    pub fn get_lifecycles(&self) -> &HashMap<String, Lifecycle> {
+        &self.lifecycles
+    }
+
+    /// 当前状态的副本,耗时的查询在副本上进行,避免长时间占用 `MONITOR`
+    pub async fn snapshot() -> Monitor {
+        let monitor = Arc::clone(&MONITOR);
+        let monitor_locked = monitor.lock().await;
+        (*monitor_locked).clone()
+    }
+
+    /// 节点状态,供 `/status` 接口使用,查询服务状态及扫描进程在 `spawn_blocking` 中进行
+    pub async fn status(&self) -> Result<api::AgentStatus, String> {
+        let config = Monitor::get_config().await;
+        let assignments = self.assignments();
+        let (supervisor, services, miners) = tokio::task::spawn_blocking(move || {
+            let supervisor = supervisor::new(config.supervisor);
+            let services = assignments
+                .into_iter()
+                .filter_map(|assignment| {
+                    let status = supervisor.status(assignment.gpu_id).ok()?;
+                    Some((assignment.gpu_id, status))
+                })
+                .collect();
+            let miners = ProcScanner::default().miners().unwrap_or_default();
+            (supervisor.name().to_string(), services, miners)
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        let hashrate = self
+            .lifecycles
+            .iter()
+            .filter_map(|(address, lifecycle)| Some((address.clone(), lifecycle.hashrate?)))
+            .collect();
+        let restarts = {
+            let policy = Arc::clone(&RESTART_POLICY);
+            let policy_locked = policy.lock().await;
+            policy_locked.records()
+        };
+        Ok(api::AgentStatus {
+            server_id: self.server_id,
+            address: self.address.clone(),
+            nvidias: self.nvidias.to_vec(),
+            driver: self.nvidias.driver.clone(),
+            supervisor,
+            services,
+            miners,
+            lifecycles: self.lifecycles.clone(),
+            hashrate,
+            restarts,
+        })
+    }
+
+    /// 处理日志分析发出的阶段事件
+    pub async fn on_event(&mut self, address: &str, event: &MinerEvent) {
+        let now = chrono::Local::now();
+        let lifecycle = self
+            .lifecycles
+            .entry(address.to_string())
+            .or_insert_with(|| Lifecycle::new(now));
+        if let Some((from, to)) = lifecycle.apply(event, now) {
+            Monitor::report_state(address, from, to).await;
         }
     }
 
+    /// 状态变化上报到中控,不等待上报完成
+    async fn report_state(address: &str, from: MinerState, to: MinerState) {
+        let body = format!("{} 状态变更:{} -> {}", address, from, to);
+        info!("{}", body);
+        Logs::spawn_upload(Massage {
+            address: address.to_string(),
+            msg_type: MsgType::REPORT,
+            body,
+        })
+        .await;
+    }
+
     pub fn get_server_id() -> Option<u32> {
         let server_id = std::env::var("SERVER_ID")
             .map_err(|e| e.to_string())
             .and_then(|server_id| server_id.parse::<u32>().map_err(|e| e.to_string()))
-            .ok();
+            .ok()
+            .or_else(|| Some(Allocation::load(&Allocation::path())?.server_id));
         if server_id.is_none() {
             error!("无法从环境变量中获取:SERVER_ID")
         }
@@ -55,7 +187,8 @@ impl Monitor {
                     .map(|s| s.trim().to_string())
                     .collect::<Vec<String>>())
             })
-            .ok();
+            .ok()
+            .or_else(|| Some(Allocation::load(&Allocation::path())?.address));
         let address = match result {
             Some(addrs) => addrs,
             None => {
@@ -70,136 +203,404 @@ impl Monitor {
         let card_number = std::env::var("CARD_NUMBER")
             .map_err(|e| e.to_string())
             .and_then(|card_number| card_number.parse::<u32>().map_err(|e| e.to_string()))
-            .ok();
+            .ok()
+            .or_else(|| Some(Allocation::load(&Allocation::path())?.card_number));
         if card_number.is_none() {
             error!("无法从环境变量中获取:CARD_NUMBER")
         }
         card_number
     }
 
-    async fn py_pros(&self) -> Result<Vec<String>, String> {
+    /// 扫描挖矿进程,需在持有 `MONITOR` 之前调用
+    pub async fn py_pros() -> Result<Vec<MinerProcess>, String> {
         info!("检测后台python挖矿程序");
-        //ps -aeo command |grep execute.py |grep -v grep
-        let mut address = Vec::new();
-        let py_proc = Command::new("ps")
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .args(["-aeo", "command"])
-            .spawn()
-            .map_err(|e| e.to_string())?
-            .stdout
-            .ok_or("ps命令运行失败！")?;
-        let grep_py = Command::new("grep")
-            .stdin(py_proc)
-            .stdout(Stdio::piped())
-            .args(["execute.py"])
-            .spawn()
-            .map_err(|e| e.to_string())?
-            .stdout
-            .ok_or("运行grep execute.py 失败！")?;
-        let grep = Command::new("grep")
-            .stdin(grep_py)
-            .stdout(Stdio::piped())
-            .args(["-v", "grep"])
-            .output()
-            .map_err(|e| e.to_string())?
-            .stdout;
-        let row = String::from_utf8(grep).map_err(|e| e.to_string())?;
-
-        info!("检测挖矿挖矿程序,程序输出:\n{}", row);
-        let reg: regex::Regex = regex::Regex::new(r"(nimble[\w]+)").map_err(|e| e.to_string())?;
-        for command in row.split("\n") {
-            if !command.is_empty() {
-                info!("{:?}", command);
-                let (_, [addr]) = reg.captures(command).ok_or("无匹配值！")?.extract::<1>();
-                address.push(addr.to_string());
-            }
+        let process = tokio::task::spawn_blocking(|| ProcScanner::default().miners())
+            .await
+            .map_err(|e| e.to_string())??;
+        for miner in process.iter() {
+            info!("{:?}", miner);
         }
-        if address.len() == 0 {
-            let massge = format!("挖矿进程已退出！");
+        if process.is_empty() {
+            let massge = "挖矿进程已退出！".to_string();
             error!("{}", massge);
             Err(massge)
         } else {
             info!(
                 "后台运行地址数量:{}个,地址信息:{}",
-                address.len(),
-                address.join(",")
+                process.len(),
+                process
+                    .iter()
+                    .map(|miner| miner.address.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(",")
             );
-            Ok(address)
+            Ok(process)
+        }
+    }
+
+    /// 检查各地址挖矿进度是否长时间无变化,卡住的地址发送重启请求,
+    /// 状态保持卡住,直到进程确实重启后才回到安装阶段
+    pub async fn check_stalls(&mut self, now: chrono::DateTime<chrono::Local>) {
+        let config = Monitor::get_config().await;
+        let stall_after = config.stall_after.unwrap_or(lifecycle::STALL_AFTER);
+        let install_after = config
+            .install_stall_after
+            .unwrap_or(lifecycle::INSTALL_STALL_AFTER);
+        let mut changes = Vec::new();
+        for (address, lifecycle) in self.lifecycles.iter_mut() {
+            if let Some((from, to)) = lifecycle.tick(
+                now,
+                chrono::Duration::seconds(stall_after),
+                chrono::Duration::seconds(install_after),
+            ) {
+                changes.push((address.clone(), from, to));
+            }
+        }
+        for (address, from, to) in changes {
+            Monitor::report_state(&address, from, to).await;
+            // 卡住的进程按重启策略处理
+            if to == MinerState::Stalled {
+                let after = if from == MinerState::Installing {
+                    install_after
+                } else {
+                    stall_after
+                };
+                let body = format!("{}阶段挖矿进度{}秒无变化", from, after);
+                Logs::send(&address, MsgType::RESTART, &body).await;
+            }
+        }
+    }
+
+    /// 挖矿进程确已重启或拉起后,地址回到安装阶段重新计时
+    async fn on_restarted(&mut self, address: &str, now: chrono::DateTime<chrono::Local>) {
+        let restarted = self
+            .lifecycles
+            .get_mut(address)
+            .and_then(|lifecycle| lifecycle.restarted(now));
+        if let Some((from, to)) = restarted {
+            Monitor::report_state(address, from, to).await;
+        }
+    }
+
+    /// 检查挖矿进程并生成处理计划,只更新状态,不停止或拉起进程,
+    /// `process` 为 `py_pros` 扫描到的挖矿进程
+    pub async fn prepare(&mut self, process: &[MinerProcess]) -> Result<Plan, String> {
+        self.check_stalls(chrono::Local::now()).await;
+
+        self.bind_gpus();
+        let plan = self.plan(process).await?;
+        for gpu in plan.gpus.iter() {
+            let missing = gpu
+                .steps
+                .iter()
+                .any(|step| matches!(step, Step::Start { .. }));
+            if let (Some(address), true) = (&gpu.address, missing) {
+                let event = MinerEvent::Failed("挖矿进程未运行".to_string());
+                self.on_event(address, &event).await;
+            }
         }
+        Ok(plan)
     }
 
-    // 本地监控
-    pub async fn mining(&self) -> Result<(), String> {
+    /// 对比地址分配与运行中的进程得出处理计划,不改变任何状态
+    pub async fn plan(&self, process: &[MinerProcess]) -> Result<Plan, String> {
         let address = self.address.clone();
         if address.len() == 0 {
             let message = format!("无法从环境变量中获取地址信息，请检查您的环境变量");
             return Err(message);
         }
-        let nvidias = self.nvidias.get_normal_nvidias();
-        let py_pros = self.py_pros().await;
-        if let Err(_) = &py_pros {
+        let nvidias = self.nvidias.to_vec();
+        if process.is_empty() {
             error!("挖矿程序检测异常，正在进行拉起nimble服务");
         }
-        let process = py_pros.unwrap_or_default();
-        // 正常运行
-        if process.len() == address.len() && process.len() == nvidias.len() {
-            info!("服务正常!!");
-            return Ok(());
-        }
-        let result = Process::new();
-        if let Err(e) = result {
-            let e = format!("pm2运行失败:{}", e);
-            error!("{}", e);
-            return Err(e);
-        }
-        let pm2 = result.unwrap();
-        #[allow(unused)]
-        let mut action = Action::SKIP;
-        for (index, addr) in address.iter().enumerate() {
-            let action_name = format!("nimble{}", index);
-            action = pm2.get_action(&action_name);
-            let _ = self.pm2(action, index, addr.clone()).await;
+        let assignments = self.assignments();
+        let mut plan = reconcile::plan(&assignments, &nvidias, process);
+        let config = Monitor::get_config().await;
+        // 主机异常已停止挖矿进程,恢复前不再拉起
+        if let Some(host) = self
+            .host
+            .as_ref()
+            .filter(|host| host.stops_miners(&config.host))
+        {
+            warn!("主机异常{:?},暂不拉起挖矿进程", host.issues);
+            for gpu in plan.gpus.iter_mut() {
+                gpu.steps.retain(|step| !matches!(step, Step::Start { .. }));
+            }
         }
-        Ok(())
+        // 重启次数过多已被停止的地址,暂停期内不再拉起
+        if config.restart.stop_on_escalate {
+            let policy = Arc::clone(&RESTART_POLICY);
+            let policy_locked = policy.lock().await;
+            let now = chrono::Local::now();
+            for gpu in plan.gpus.iter_mut() {
+                let address = gpu.address.clone().unwrap_or_default();
+                if (*policy_locked).is_escalated(&address, now) {
+                    gpu.steps.retain(|step| !matches!(step, Step::Start { .. }));
+                }
+            }
+        }
+        Ok(plan)
     }
 
-    async fn pm2(&self, action: pm::Action, index: usize, address: String) -> Result<(), String> {
-        let dir = std::env::current_dir().unwrap().join("execute.sh");
-        let mut bash = std::process::Command::new("bash");
-        bash.stdout(Stdio::null()).stderr(Stdio::null());
-
-        match action {
-            pm::Action::START => {
-                bash.args([
-                    dir.to_str().unwrap(),
-                    "start",
-                    index.to_string().as_str(),
-                    &address,
-                ]);
+    /// 执行处理计划,会调用外部命令,需在 `spawn_blocking` 中运行
+    pub fn apply(supervisor: &dyn Supervisor, plan: &Plan) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for miner in plan.stray.iter() {
+            if let Err(e) = Monitor::stop(supervisor, miner.pid, miner.pm2_id) {
+                errors.push(format!("停止进程{}失败:{}", miner.pid, e));
             }
-            pm::Action::RESTART => {
-                info!("正在重启挖矿程序!");
-                bash.args([dir.to_str().unwrap(), "restart", index.to_string().as_str()]);
+        }
+        for gpu in plan.gpus.iter() {
+            if let Err(e) = Monitor::reconcile(supervisor, gpu) {
+                errors.push(format!(
+                    "显卡{}处理失败({}):{}",
+                    gpu.gpu_id,
+                    supervisor.name(),
+                    e
+                ));
             }
-            pm::Action::SKIP => {
-                bash.args(["echo", "'done'"]);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// 按计划处理单张显卡:先停止多余进程,再拉起分配的地址
+    fn reconcile(supervisor: &dyn Supervisor, gpu: &GpuPlan) -> Result<(), String> {
+        let mut stopped = false;
+        for step in gpu.steps.iter() {
+            match step {
+                Step::Stop {
+                    pid,
+                    pm2_id,
+                    address,
+                    reason,
+                } => {
+                    warn!(
+                        "显卡{}停止挖矿进程{}({}):{}",
+                        gpu.gpu_id, pid, address, reason
+                    );
+                    stopped |= Monitor::stop(supervisor, *pid, *pm2_id)? == Some(gpu.gpu_id);
+                }
+                Step::Start { address } => {
+                    info!("显卡{}({})空闲,拉起地址:{}", gpu.gpu_id, gpu.uuid, address);
+                    // 同名服务已删除时需重新创建
+                    let status = if stopped {
+                        Status::Missing
+                    } else {
+                        supervisor.status(gpu.gpu_id)?
+                    };
+                    match status {
+                        Status::Missing => supervisor.start(gpu.gpu_id, address)?,
+                        Status::Stopped => supervisor.restart(gpu.gpu_id)?,
+                        Status::Online => info!("服务{}运行中,等待挖矿进程启动", gpu.gpu_id),
+                        Status::CrashLoop => {
+                            warn!("服务{}反复崩溃,暂停期间不拉起", gpu.gpu_id);
+                            continue;
+                        }
+                    }
+                    info!("已重新拉起挖矿程序！");
+                }
             }
         }
-        let _ = bash.spawn().map_err(|e| e.to_string())?.wait();
-        info!("已重新拉起挖矿程序！");
         Ok(())
     }
 
-    pub async fn dispatch(&mut self) {
-        //监控是否掉线
+    /// 停止挖矿进程,由后端管理的服务通过后端删除,其余直接结束进程,
+    /// 返回被删除服务的显卡序号
+    fn stop(
+        supervisor: &dyn Supervisor,
+        pid: u32,
+        pm2_id: Option<u32>,
+    ) -> Result<Option<u32>, String> {
+        match supervisor.owner(pid, pm2_id) {
+            Some(card_number) => {
+                supervisor.stop(card_number)?;
+                Ok(Some(card_number))
+            }
+            None => {
+                supervisor.kill(pid)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// 地址所在显卡的运行数据汇总
+    pub fn gpu_summary(&self, address: &str) -> Option<GpuSummary> {
+        let assignment = self
+            .assignments()
+            .into_iter()
+            .find(|assignment| assignment.address == address)?;
+        self.telemetry.summary(&assignment.uuid)
+    }
+
+    /// 停止本机已分配显卡的挖矿进程
+    pub async fn stop_miners(reason: &str) {
+        let gpus = {
+            let monitor = Arc::clone(&MONITOR);
+            let monitor_locked = monitor.lock().await;
+            monitor_locked
+                .address
+                .iter()
+                .filter_map(|address| monitor_locked.gpu_of(address))
+                .collect::<Vec<u32>>()
+        };
+        warn!("{},停止挖矿进程:{:?}", reason, gpus);
+        let kind = Monitor::get_config().await.supervisor;
+        let _ = tokio::task::spawn_blocking(move || {
+            let supervisor = supervisor::new(kind);
+            for gpu_id in gpus {
+                if let Err(e) = supervisor.stop(gpu_id) {
+                    error!("显卡{}停止失败({}):{}", gpu_id, supervisor.name(), e);
+                }
+            }
+        })
+        .await;
+    }
+
+    /// 退出时保存的节点状态,供排查问题
+    pub fn state_path() -> std::path::PathBuf {
+        std::env::current_dir()
+            .unwrap()
+            .join("run")
+            .join("monitor.json")
+    }
+
+    pub fn save_state(&self, path: &std::path::Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(path, &content)
+    }
+
+    /// 退出前上报并保存状态,按配置停止或保留挖矿进程
+    async fn shutdown() {
+        let config = Monitor::get_config().await;
+        let body = match config.on_shutdown {
+            ShutdownAction::Detach => "节点程序退出,挖矿进程继续运行",
+            ShutdownAction::Stop => "节点程序退出,停止挖矿进程",
+        };
+        warn!("{}", body);
+        Logs::spawn_upload(Massage {
+            address: "host".to_string(),
+            msg_type: MsgType::REPORT,
+            body: body.to_string(),
+        })
+        .await;
+        if config.on_shutdown == ShutdownAction::Stop {
+            Monitor::stop_miners("节点程序退出").await;
+        }
+        {
+            let monitor = Arc::clone(&MONITOR);
+            let monitor_locked = monitor.lock().await;
+            let path = Monitor::state_path();
+            match monitor_locked.save_state(&path) {
+                Ok(()) => info!("节点状态已保存:{}", path.display()),
+                Err(e) => error!("保存节点状态失败:{}", e),
+            }
+        }
+        Logs::flush(SHUTDOWN_TIMEOUT).await;
+    }
 
-        let result = self.mining().await;
-        if let Err(e) = result {
-            error!("调用程序失败:{}", e);
+    /// 地址分配到的显卡序号
+    pub fn gpu_of(&self, address: &str) -> Option<u32> {
+        self.assignments()
+            .into_iter()
+            .find(|assignment| assignment.address == address)
+            .map(|assignment| assignment.gpu_id)
+    }
+
+    /// 处理日志分析发出的重启请求,按重启策略决定重启、忽略或上报并停止,
+    /// 只有确实重启后才重置该地址的阶段
+    pub async fn restart(gpu_id: Option<u32>, msg: &Massage) {
+        let config = Monitor::get_config().await;
+        let decision = {
+            let policy = Arc::clone(&RESTART_POLICY);
+            let mut policy_locked = policy.lock().await;
+            (*policy_locked).decide(
+                &config.restart,
+                &msg.address,
+                &msg.body,
+                chrono::Local::now(),
+            )
+        };
+        info!(
+            "地址{}重启决策:{:?},原因:{}",
+            msg.address, decision, msg.body
+        );
+        let Some(gpu_id) = gpu_id else {
+            warn!("地址{}未分配显卡,无法处理重启", msg.address);
+            return;
+        };
+        let stop = match decision {
+            Decision::Restart => false,
+            Decision::Escalate => {
+                let body = format!(
+                    "地址{}在一小时内重启超过{}次,最近原因:{}",
+                    msg.address, config.restart.max_per_hour, msg.body
+                );
+                error!("{}", body);
+                Logs::upload(Massage {
+                    address: msg.address.clone(),
+                    msg_type: MsgType::REPORT,
+                    body,
+                })
+                .await;
+                if !config.restart.stop_on_escalate {
+                    return;
+                }
+                true
+            }
+            Decision::Cooldown { .. } | Decision::Suppressed => return,
+        };
+        let kind = config.supervisor;
+        let address = msg.address.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let supervisor = supervisor::new(kind);
+            let result = if stop {
+                supervisor.stop(gpu_id)
+            } else {
+                match supervisor.status(gpu_id) {
+                    Ok(Status::Missing) => supervisor.start(gpu_id, &address),
+                    Ok(Status::CrashLoop) => Err(format!("服务{}反复崩溃,暂停期间不重启", gpu_id)),
+                    Ok(_) => supervisor.restart(gpu_id),
+                    Err(e) => Err(e),
+                }
+            };
+            result.map_err(|e| format!("{}:{}", supervisor.name(), e))
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+        match result {
+            Ok(()) if !stop => {
+                let monitor = Arc::clone(&MONITOR);
+                let mut monitor_locked = monitor.lock().await;
+                (*monitor_locked)
+                    .on_restarted(&msg.address, chrono::Local::now())
+                    .await;
+            }
+            Ok(()) => {}
+            Err(e) => error!("显卡{}处理重启失败({})", gpu_id, e),
         }
     }
 
+    /// 按配置的来源识别显卡
+    pub async fn detect_gpus() -> Result<GeForces, String> {
+        let backend = nvidia::new(&Monitor::get_config().await.gpu);
+        tokio::task::spawn_blocking(move || {
+            backend
+                .discover()
+                .map_err(|e| format!("{}:{}", backend.name(), e))
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+    }
+
     pub async fn get_config() -> crate::config::Monitor {
         let config = Arc::clone(&CONFIG);
         let config_locked = config.lock().await;
@@ -207,31 +608,46 @@ impl Monitor {
     }
 }
 
+/// 节点主程序:各任务独立运行,任务退出或 panic 后由 `task::supervise` 重新启动,
+/// 收到 SIGTERM/SIGINT 后各任务完成当前处理后退出
 pub async fn monitor() {
-    // tokio::spawn(log::Logs::monitor());
-
-    loop {
+    tokio::spawn(shutdown::listen());
+    // 先识别显卡,获取地址及首次检查时需要显卡数量
+    let nvidias = Monitor::detect_gpus().await.unwrap_or_else(|e| {
+        error!("获取显卡信息失败:{}", e);
+        GeForces::default()
+    });
+    {
         let monitor = Arc::clone(&MONITOR);
         let mut monitor_locked = monitor.lock().await;
-        let log_channel = Arc::clone(&LOG_CHANNEL);
-        let mut log_channel_locked = log_channel.lock().await;
-
-        tokio::select! {
-            _ = (*monitor_locked).dispatch() => {
-
-            },
-            Some(msg) = (*log_channel_locked).1.recv() =>{
-                match msg.msg_type {
-                    log::MsgType::RESTART => {
-                        warn!("需要重启:{:?}",msg);
-                    },
-                    _=>{}
-                }
-            }
-        }
-
-        // (*monitor_locked).dispatch().await;
-        drop(monitor_locked);
-        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        monitor_locked.nvidias = nvidias.clone();
+    }
+    bootstrap::bootstrap().await;
+    let (nvidias, _) = watch::channel(nvidias);
+    let nvidias = Arc::new(nvidias);
+    let poller = Arc::clone(&nvidias);
+    let tasks = vec![
+        tokio::spawn(task::supervise("显卡检测", move || {
+            task::poll_gpus(Arc::clone(&poller))
+        })),
+        tokio::spawn(task::supervise("进程检查", move || {
+            task::reconcile(nvidias.subscribe())
+        })),
+        tokio::spawn(task::supervise("日志读取", Logs::monitor)),
+        tokio::spawn(task::supervise("消息处理", task::handle_messages)),
+        tokio::spawn(task::supervise("心跳上报", heartbeat::report)),
+        tokio::spawn(task::supervise("主机检查", host::watch)),
+        tokio::spawn(task::supervise("显卡采样", telemetry::watch)),
+        tokio::spawn(task::supervise("http服务", api::serve)),
+    ];
+    let tasks = futures::future::join_all(tasks);
+    tokio::select! {
+        _ = tasks => {}
+        _ = async {
+            shutdown::wait().await;
+            tokio::time::sleep(SHUTDOWN_TIMEOUT).await;
+        } => warn!("部分任务未在{}秒内退出", SHUTDOWN_TIMEOUT.as_secs()),
     }
+    Monitor::shutdown().await;
+    info!("节点程序已退出");
 }
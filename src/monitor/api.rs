@@ -0,0 +1,163 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use actix_web::{get, web, App, HttpResponse, HttpServer};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::{
+    lifecycle::Lifecycle,
+    nvidia::{Driver, GeForce},
+    policy::Record,
+    procfs::MinerProcess,
+    supervisor::Status,
+    Monitor,
+};
+use crate::shutdown;
+
+/// 默认监听端口,与下单时映射的 http 端口一致
+pub const HTTP_PORT: u16 = 8888;
+/// `/logs` 默认及最多返回的行数
+const DEFAULT_TAIL: usize = 100;
+const MAX_TAIL: usize = 5000;
+
+/// `/status` 返回的节点状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub server_id: Option<u32>,
+    pub address: Vec<String>,
+    pub nvidias: Vec<GeForce>,
+    /// 显卡驱动及 CUDA 版本
+    pub driver: Option<Driver>,
+    /// 进程管理后端
+    pub supervisor: String,
+    /// 各显卡服务状态,key 为显卡序号
+    pub services: BTreeMap<u32, Status>,
+    /// 正在运行的挖矿进程
+    pub miners: Vec<MinerProcess>,
+    pub lifecycles: HashMap<String, Lifecycle>,
+    /// 各地址最近一次算力(it/s)
+    pub hashrate: BTreeMap<String, f32>,
+    /// 最近的重启决策记录,从旧到新
+    #[serde(default)]
+    pub restarts: Vec<Record>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailQuery {
+    pub tail: Option<usize>,
+}
+
+#[get("/healthz")]
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// 查询服务状态及扫描进程较慢,在 `Monitor::snapshot` 的副本上进行,不阻塞其他任务
+#[get("/status")]
+pub async fn status() -> HttpResponse {
+    match Monitor::snapshot().await.status().await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    match super::metrics::collect(&Monitor::snapshot().await).await {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+#[get("/logs/{address}")]
+pub async fn logs(address: web::Path<String>, query: web::Query<TailQuery>) -> HttpResponse {
+    let address = address.into_inner();
+    // 只允许读取 logs 目录下的地址日志
+    let valid = regex::Regex::new(r"^nimble\w+$").unwrap();
+    if !valid.is_match(&address) {
+        return HttpResponse::BadRequest().body(format!("无效的地址:{}", address));
+    }
+    let tail = query.tail.unwrap_or(DEFAULT_TAIL).min(MAX_TAIL);
+    let path = match std::env::current_dir() {
+        Ok(workdir) => workdir.join("logs").join(format!("{}.txt", address)),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("获取工作目录失败:{}", e))
+        }
+    };
+    match tail_lines(&path, tail) {
+        Ok(lines) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(lines.join("\n")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().body(format!("日志不存在:{}", address))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// 从文件末尾向前读取最后 `n` 行
+pub fn tail_lines(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+    const BLOCK: u64 = 8192;
+    let mut file = std::fs::File::open(path)?;
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut pos = len;
+    let mut buf = Vec::new();
+    // 多读一行,保证第一行完整
+    while pos > 0 && buf.iter().filter(|b| **b == b'\n').count() <= n {
+        let size = BLOCK.min(pos);
+        pos -= size;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut block = vec![0u8; size as usize];
+        file.read_exact(&mut block)?;
+        block.extend(buf);
+        buf = block;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let lines = text
+        .trim_end_matches('\n')
+        .split('\n')
+        .collect::<Vec<&str>>();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|line| line.to_string()).collect())
+}
+
+/// 节点http服务,中控通过订单的 http_port 查询
+pub async fn serve() {
+    let port = Monitor::get_config().await.http_port.unwrap_or(HTTP_PORT);
+    info!("节点http服务监听端口:{}", port);
+    let server = HttpServer::new(|| {
+        App::new()
+            .service(healthz)
+            .service(status)
+            .service(metrics)
+            .service(logs)
+    })
+    .workers(1)
+    // 退出信号由 shutdown::listen 统一处理
+    .disable_signals()
+    .bind(("0.0.0.0", port))
+    .map(|server| server.run());
+    let result = match server {
+        Ok(server) => {
+            let handle = server.handle();
+            tokio::spawn(async move {
+                shutdown::wait().await;
+                handle.stop(true).await;
+            });
+            server.await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        error!("节点http服务启动失败:{}", e);
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, path::Path, path::PathBuf, sync::Arc};
+
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use super::{lifecycle::Lifecycle, Monitor, MONITOR};
+use crate::config::snapshot::write_atomic;
+
+/// 中控分配的地址,保存后重启时无需环境变量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub server_id: u32,
+    pub card_number: u32,
+    pub address: Vec<String>,
+    /// 地址绑定的显卡UUID,显卡重新枚举或识别失败时地址不随显卡序号变化,
+    /// 更换显卡后需删除对应的绑定
+    #[serde(default)]
+    pub gpus: BTreeMap<String, String>,
+}
+
+impl Allocation {
+    /// 保存位置
+    pub fn path() -> PathBuf {
+        std::env::current_dir()
+            .unwrap()
+            .join("run")
+            .join("allocation.json")
+    }
+
+    pub fn load(path: &Path) -> Option<Allocation> {
+        let content = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str::<Allocation>(&content)
+            .map_err(|e| error!("读取地址分配失败:{}: {}", path.display(), e))
+            .ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        write_atomic(path, &serde_json::to_string_pretty(self).unwrap())
+    }
+}
+
+/// 调用中控 `/distribute_address/{card_number}/{server_id}` 获取地址
+pub async fn fetch(api: &str, card_number: u32, server_id: u32) -> Result<Vec<String>, String> {
+    let url = format!("{}/{}/{}", api, card_number, server_id);
+    let response = ClientBuilder::new()
+        .build()
+        .map_err(|e| e.to_string())?
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("{} {}: {}", url, status, body));
+    }
+    response
+        .json::<Vec<String>>()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 环境变量不完整时向中控获取地址
+pub async fn bootstrap() {
+    let complete = ["SERVER_ID", "ADDRESS", "CARD_NUMBER"]
+        .iter()
+        .all(|key| std::env::var(key).is_ok());
+    if complete {
+        return;
+    }
+    warn!("环境变量不完整,向中控获取地址");
+    if let Err(e) = refresh().await {
+        error!("获取地址失败:{}", e);
+    }
+}
+
+/// 按本机显卡数量重新获取地址,地址变化时更新并保存
+pub async fn refresh() -> Result<(), String> {
+    let (server_id, card_number) = {
+        let monitor = Arc::clone(&MONITOR);
+        let monitor_locked = monitor.lock().await;
+        let card_number = monitor_locked.nvidias.get_normal_nvidias().len() as u32;
+        (monitor_locked.server_id, card_number)
+    };
+    let server_id = server_id.ok_or("无法获取SERVER_ID".to_string())?;
+    if card_number == 0 {
+        return Err("未识别到显卡".to_string());
+    }
+    let api = Monitor::get_config()
+        .await
+        .central_url("distribute_address");
+    let address = fetch(&api, card_number, server_id).await?;
+    let monitor = Arc::clone(&MONITOR);
+    let mut monitor_locked = monitor.lock().await;
+    if (*monitor_locked).set_address(&address) {
+        info!("地址已更新:{}", address.join(","));
+    }
+    let allocation = Allocation {
+        server_id,
+        card_number,
+        address,
+        gpus: monitor_locked.gpus.clone(),
+    };
+    drop(monitor_locked);
+    allocation.save(&Allocation::path())
+}
+
+impl Monitor {
+    /// 更新地址,返回是否有变化
+    pub fn set_address(&mut self, address: &[String]) -> bool {
+        if self.address == address {
+            return false;
+        }
+        let now = chrono::Local::now();
+        self.lifecycles.retain(|addr, _| address.contains(addr));
+        self.gpus.retain(|addr, _| address.contains(addr));
+        for addr in address.iter() {
+            self.lifecycles
+                .entry(addr.clone())
+                .or_insert_with(|| Lifecycle::new(now));
+        }
+        self.address = address.to_vec();
+        true
+    }
+
+    /// 为未绑定的地址绑定分配到的显卡,有新绑定时保存到地址分配文件
+    pub fn bind_gpus(&mut self) {
+        let assignments = self.assignments();
+        let mut changed = false;
+        for assignment in assignments.iter() {
+            if !self.gpus.contains_key(&assignment.address) {
+                info!(
+                    "地址{}绑定显卡{}({})",
+                    assignment.address, assignment.gpu_id, assignment.uuid
+                );
+                self.gpus
+                    .insert(assignment.address.clone(), assignment.uuid.clone());
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+        let path = Allocation::path();
+        let allocation = match (Allocation::load(&path), self.server_id) {
+            (Some(allocation), _) => Allocation {
+                gpus: self.gpus.clone(),
+                ..allocation
+            },
+            (None, Some(server_id)) => Allocation {
+                server_id,
+                card_number: self.nvidias.get_normal_nvidias().len() as u32,
+                address: self.address.clone(),
+                gpus: self.gpus.clone(),
+            },
+            (None, None) => {
+                warn!("缺少SERVER_ID,显卡绑定不保存");
+                return;
+            }
+        };
+        if let Err(e) = allocation.save(&path) {
+            error!("保存显卡绑定失败:{}", e);
+        }
+    }
+}
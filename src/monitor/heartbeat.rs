@@ -0,0 +1,175 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use hmac::{Hmac, Mac};
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{error, info, warn};
+
+use super::{
+    bootstrap, host::HostHealth, lifecycle::MinerState, nvidia::GeForce, telemetry::GpuSummary,
+    Monitor, MONITOR,
+};
+use crate::shutdown;
+
+/// 签名所在的请求头,值为 hex 编码的 HMAC-SHA256
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+/// 心跳时间与中控时间最多相差的秒数,防止重放
+pub const MAX_SKEW: i64 = 300;
+
+/// 地址当前状态及算力
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinerReport {
+    pub state: MinerState,
+    pub hashrate: Option<f32>,
+    /// 最近一次进度变化的时间(秒)
+    #[serde(default)]
+    pub last_progress: i64,
+}
+
+/// 节点定时上报给中控的心跳
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub server_id: u32,
+    pub addresses: Vec<String>,
+    /// 识别到的显卡 UUID
+    pub gpus: Vec<String>,
+    pub miners: BTreeMap<String, MinerReport>,
+    pub version: String,
+    /// 发送时间(秒)
+    pub timestamp: i64,
+    /// 主机资源及异常
+    #[serde(default)]
+    pub host: Option<HostHealth>,
+    /// 各显卡最近一段时间的运行数据汇总,key 为显卡 UUID
+    #[serde(default)]
+    pub telemetry: BTreeMap<String, GpuSummary>,
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 校验签名并解析心跳
+pub fn verify(secret: &str, body: &[u8], signature: &str, now: i64) -> Result<Heartbeat, String> {
+    if secret.is_empty() {
+        return Err("未配置心跳签名密钥".to_string());
+    }
+    let signature = hex::decode(signature).map_err(|_| "签名格式错误".to_string())?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    mac.verify_slice(&signature)
+        .map_err(|_| "签名校验失败".to_string())?;
+    let heartbeat = serde_json::from_slice::<Heartbeat>(body).map_err(|e| e.to_string())?;
+    if (now - heartbeat.timestamp).abs() > MAX_SKEW {
+        return Err(format!("心跳时间相差过大:{}秒", now - heartbeat.timestamp));
+    }
+    Ok(heartbeat)
+}
+
+impl Monitor {
+    pub fn heartbeat(&self, now: i64) -> Option<Heartbeat> {
+        let server_id = self.server_id?;
+        let gpus = self
+            .nvidias
+            .iter()
+            .filter_map(|nvidia| match nvidia {
+                GeForce::CARD { uuid, .. } => Some(uuid.clone()),
+                GeForce::ERROR(_) => None,
+            })
+            .collect();
+        let miners = self
+            .lifecycles
+            .iter()
+            .map(|(address, lifecycle)| {
+                let report = MinerReport {
+                    state: lifecycle.state,
+                    hashrate: lifecycle.hashrate,
+                    last_progress: lifecycle.last_progress.timestamp(),
+                };
+                (address.clone(), report)
+            })
+            .collect();
+        Some(Heartbeat {
+            server_id,
+            addresses: self.address.clone(),
+            gpus,
+            miners,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: now,
+            host: self.host.clone(),
+            telemetry: self.telemetry.summaries(),
+        })
+    }
+}
+
+/// 定时向中控上报心跳
+pub async fn report() {
+    loop {
+        let config = Monitor::get_config().await;
+        let secret = config.heartbeat.secret.expose().clone();
+        if secret.is_empty() {
+            warn!("未配置 monitor.heartbeat.secret,不上报心跳");
+        } else {
+            let heartbeat = {
+                let monitor = Arc::clone(&MONITOR);
+                let monitor_locked = monitor.lock().await;
+                (*monitor_locked).heartbeat(chrono::Local::now().timestamp())
+            };
+            match heartbeat {
+                Some(heartbeat) => {
+                    let updated = send(&config.heartbeat_url(), &secret, &heartbeat).await;
+                    // 中控未确认的地址已被重新分配,重新获取
+                    let reassigned = updated.is_some_and(|updated| {
+                        heartbeat.addresses.is_empty()
+                            || heartbeat
+                                .addresses
+                                .iter()
+                                .any(|address| !updated.contains(address))
+                    });
+                    if reassigned {
+                        warn!("中控未确认本机地址,重新获取地址");
+                        if let Err(e) = bootstrap::refresh().await {
+                            error!("获取地址失败:{}", e);
+                        }
+                    }
+                }
+                None => warn!("缺少SERVER_ID,不上报心跳"),
+            }
+        }
+        let interval = std::time::Duration::from_secs(config.heartbeat.interval);
+        if shutdown::sleep(interval).await {
+            return;
+        }
+    }
+}
+
+/// 发送心跳,返回中控确认的地址
+async fn send(api: &str, secret: &str, heartbeat: &Heartbeat) -> Option<Vec<String>> {
+    let body = serde_json::to_vec(heartbeat).unwrap();
+    let client = ClientBuilder::new().build().unwrap();
+    let result = client
+        .post(api)
+        .header(SIGNATURE_HEADER, sign(secret, &body))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let updated = match result {
+        Ok(response) => response.json::<Vec<String>>().await,
+        Err(e) => Err(e),
+    };
+    match updated {
+        Ok(updated) => {
+            info!("心跳上报成功:{}", api);
+            Some(updated)
+        }
+        Err(e) => {
+            error!("心跳上报失败:{:?}", e);
+            None
+        }
+    }
+}
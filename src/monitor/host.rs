@@ -0,0 +1,383 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tracing::{error, info, warn};
+
+use super::lifecycle::MinerState;
+use super::{Monitor, MONITOR};
+use crate::config::{Host, HostAction};
+use crate::log::{Logs, Massage, MsgType};
+use crate::shutdown;
+
+const MB: u64 = 1024 * 1024;
+
+/// 主机异常
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum HostIssue {
+    /// 挖矿目录所在磁盘空间不足
+    DiskFull,
+    /// 可用内存不足
+    LowMemory,
+    /// 负载过高
+    Overload,
+    /// 有进程被 OOM 结束
+    OomKill,
+}
+
+/// 主机资源采样,随心跳上报
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostHealth {
+    /// 挖矿目录所在磁盘的剩余及总空间(字节)
+    pub disk_free: u64,
+    pub disk_total: u64,
+    pub mem_available: u64,
+    pub mem_total: u64,
+    pub swap_free: u64,
+    pub swap_total: u64,
+    /// 1/5/15分钟平均负载
+    pub load: [f32; 3],
+    pub cpus: usize,
+    /// 系统启动以来被 OOM 结束的进程数
+    pub oom_kills: u64,
+    /// 最近一次 OOM 的内核日志
+    pub last_oom: Option<String>,
+    pub issues: Vec<HostIssue>,
+}
+
+impl HostHealth {
+    /// 当前异常是否要求停止挖矿进程
+    pub fn stops_miners(&self, config: &Host) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| actions_of(config, *issue).contains(&HostAction::StopMiners))
+    }
+}
+
+/// 读取 /proc 的主机信息,根目录可替换为测试目录
+#[derive(Debug, Clone)]
+pub struct HostScanner {
+    root: PathBuf,
+}
+
+impl Default for HostScanner {
+    fn default() -> Self {
+        HostScanner::new("/proc")
+    }
+}
+
+impl HostScanner {
+    pub fn new(root: impl Into<PathBuf>) -> HostScanner {
+        HostScanner { root: root.into() }
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.root.join(name)).unwrap_or_default()
+    }
+
+    /// /proc/meminfo 中的值(kB),返回字节
+    fn meminfo(&self) -> Vec<(String, u64)> {
+        self.read("meminfo")
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                let kb = value.split_whitespace().next()?.parse::<u64>().ok()?;
+                Some((key.trim().to_string(), kb * 1024))
+            })
+            .collect()
+    }
+
+    fn loadavg(&self) -> [f32; 3] {
+        let content = self.read("loadavg");
+        let mut load = [0f32; 3];
+        for (index, value) in content.split_whitespace().take(3).enumerate() {
+            load[index] = value.parse::<f32>().unwrap_or_default();
+        }
+        load
+    }
+
+    fn cpus(&self) -> usize {
+        let cpus = self
+            .read("cpuinfo")
+            .lines()
+            .filter(|line| line.starts_with("processor"))
+            .count();
+        if cpus > 0 {
+            return cpus;
+        }
+        std::thread::available_parallelism()
+            .map(|cpus| cpus.get())
+            .unwrap_or(1)
+    }
+
+    fn oom_kills(&self) -> u64 {
+        self.read("vmstat")
+            .lines()
+            .find_map(|line| line.strip_prefix("oom_kill "))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or_default()
+    }
+
+    /// 采样,磁盘空间通过 df 查询
+    pub fn sample(&self, dir: &Path) -> HostHealth {
+        let meminfo = self.meminfo();
+        let mem = |key: &str| {
+            meminfo
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| *value)
+                .unwrap_or_default()
+        };
+        let (disk_free, disk_total) = disk_usage(dir).unwrap_or_else(|e| {
+            warn!("查询磁盘空间失败:{}", e);
+            (0, 0)
+        });
+        HostHealth {
+            disk_free,
+            disk_total,
+            mem_available: mem("MemAvailable"),
+            mem_total: mem("MemTotal"),
+            swap_free: mem("SwapFree"),
+            swap_total: mem("SwapTotal"),
+            load: self.loadavg(),
+            cpus: self.cpus(),
+            oom_kills: self.oom_kills(),
+            last_oom: None,
+            issues: Vec::new(),
+        }
+    }
+}
+
+/// 目录所在磁盘的剩余及总空间(字节)
+fn disk_usage(dir: &Path) -> Result<(u64, u64), String> {
+    let output = Command::new("df")
+        .args(["-B1", "--output=avail,size"])
+        .arg(dir)
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let values = stdout
+        .lines()
+        .nth(1)
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|value| value.parse::<u64>().ok())
+        .collect::<Vec<u64>>();
+    match values[..] {
+        [free, total] => Ok((free, total)),
+        _ => Err(format!("无法解析df输出:{}", stdout.trim())),
+    }
+}
+
+/// 内核日志中最近一次 OOM 结束进程的记录
+fn last_oom() -> Option<String> {
+    let output = Command::new("dmesg").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .rev()
+        .find(|line| line.contains("Killed process") || line.contains("Out of memory"))
+        .map(|line| line.trim().to_string())
+}
+
+fn actions_of(config: &Host, issue: HostIssue) -> &[HostAction] {
+    match issue {
+        HostIssue::DiskFull => &config.on_disk_full,
+        HostIssue::LowMemory => &config.on_low_memory,
+        HostIssue::Overload => &config.on_overload,
+        HostIssue::OomKill => &config.on_oom_kill,
+    }
+}
+
+/// 按阈值判断异常,OOM 次数与上次采样比较
+pub fn issues(config: &Host, health: &HostHealth, previous: Option<&HostHealth>) -> Vec<HostIssue> {
+    let mut issues = Vec::new();
+    if health.disk_total > 0 && health.disk_free < config.min_disk_free * MB {
+        issues.push(HostIssue::DiskFull);
+    }
+    if health.mem_total > 0 && health.mem_available < config.min_mem_available * MB {
+        issues.push(HostIssue::LowMemory);
+    }
+    if health.cpus > 0 && health.load[0] / health.cpus as f32 > config.max_load {
+        issues.push(HostIssue::Overload);
+    }
+    if previous.is_some_and(|previous| health.oom_kills > previous.oom_kills) {
+        issues.push(HostIssue::OomKill);
+    }
+    issues
+}
+
+/// 新出现的异常需要执行的处理,持续存在的异常不重复处理
+pub fn actions(config: &Host, issues: &[HostIssue], previous: &[HostIssue]) -> Vec<HostAction> {
+    let mut actions = Vec::new();
+    for issue in issues.iter() {
+        if *issue != HostIssue::OomKill && previous.contains(issue) {
+            continue;
+        }
+        for action in actions_of(config, *issue) {
+            if !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+    }
+    actions
+}
+
+/// 解析待清理的缓存目录,只允许 $HOME/.cache 或工作目录下的子目录
+pub fn cache_paths(cache_dirs: &[String], home: &Path, workdir: &Path) -> Vec<PathBuf> {
+    let roots = [home.join(".cache"), workdir.to_path_buf()]
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .collect::<Vec<PathBuf>>();
+    let mut paths = Vec::new();
+    for dir in cache_dirs.iter() {
+        let path = match dir.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(dir),
+        };
+        // 不存在的目录无需清理,存在的解析符号链接及 .. 后再比较
+        let path = match fs::canonicalize(&path) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        if roots
+            .iter()
+            .any(|root| path != *root && path.starts_with(root))
+        {
+            paths.push(path);
+        } else {
+            warn!("缓存目录{}不在{:?}下,拒绝清理", path.display(), roots);
+        }
+    }
+    paths
+}
+
+/// 删除缓存目录,返回释放的目录
+fn clean(cache_dirs: &[String], workdir: &Path) -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let mut cleaned = Vec::new();
+    for path in cache_paths(cache_dirs, Path::new(&home), workdir) {
+        match fs::remove_dir_all(&path) {
+            Ok(()) => cleaned.push(path),
+            Err(e) => error!("清理缓存{}失败:{}", path.display(), e),
+        }
+    }
+    cleaned
+}
+
+/// 定时检查主机资源,异常时按配置处理
+pub async fn watch() {
+    let scanner = HostScanner::default();
+    let mut previous: Option<HostHealth> = None;
+    loop {
+        let config = Monitor::get_config().await;
+        let workdir = std::env::current_dir().unwrap();
+        let miner_dir = workdir.join("nimble-miner-public");
+        let dir = if miner_dir.exists() {
+            miner_dir
+        } else {
+            workdir
+        };
+        let sampler = scanner.clone();
+        let mut health = match tokio::task::spawn_blocking(move || sampler.sample(&dir)).await {
+            Ok(health) => health,
+            Err(e) => {
+                error!("主机检查失败:{}", e);
+                let interval = std::time::Duration::from_secs(config.host.interval);
+                if shutdown::sleep(interval).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        health.issues = issues(&config.host, &health, previous.as_ref());
+        if health.issues.contains(&HostIssue::OomKill) {
+            health.last_oom = tokio::task::spawn_blocking(last_oom).await.ok().flatten();
+        }
+        let previous_issues = previous
+            .as_ref()
+            .map(|previous| previous.issues.clone())
+            .unwrap_or_default();
+        let actions = actions(&config.host, &health.issues, &previous_issues);
+        for action in actions.iter() {
+            handle(action, &config, &health).await;
+        }
+        {
+            let monitor = Arc::clone(&MONITOR);
+            let mut monitor_locked = monitor.lock().await;
+            monitor_locked.host = Some(health.clone());
+        }
+        previous = Some(health);
+        if shutdown::sleep(std::time::Duration::from_secs(config.host.interval)).await {
+            return;
+        }
+    }
+}
+
+async fn handle(action: &HostAction, config: &crate::config::Monitor, health: &HostHealth) {
+    match action {
+        HostAction::Clean => {
+            if config.host.cache_dirs.is_empty() {
+                return;
+            }
+            // 下载模型或训练中的进程可能正在使用缓存
+            let busy = {
+                let monitor = Arc::clone(&MONITOR);
+                let monitor_locked = monitor.lock().await;
+                monitor_locked
+                    .get_lifecycles()
+                    .iter()
+                    .filter(|(_, lifecycle)| {
+                        matches!(
+                            lifecycle.state,
+                            MinerState::Downloading | MinerState::Training
+                        )
+                    })
+                    .map(|(address, _)| address.clone())
+                    .collect::<Vec<String>>()
+            };
+            if !busy.is_empty() {
+                info!("挖矿进程下载或训练中,跳过清理缓存:{:?}", busy);
+                return;
+            }
+            let workdir = match std::env::current_dir() {
+                Ok(workdir) => workdir,
+                Err(e) => {
+                    error!("获取工作目录失败,跳过清理缓存:{}", e);
+                    return;
+                }
+            };
+            let cache_dirs = config.host.cache_dirs.clone();
+            if let Ok(cleaned) =
+                tokio::task::spawn_blocking(move || clean(&cache_dirs, &workdir)).await
+            {
+                info!("已清理缓存:{:?}", cleaned);
+            }
+        }
+        HostAction::StopMiners => {
+            Monitor::stop_miners(&format!("主机异常{:?}", health.issues)).await;
+        }
+        HostAction::Flag => {
+            let body = format!(
+                "主机异常:{:?},磁盘剩余:{}MB,可用内存:{}MB,负载:{:?},OOM次数:{},{}",
+                health.issues,
+                health.disk_free / MB,
+                health.mem_available / MB,
+                health.load,
+                health.oom_kills,
+                health.last_oom.clone().unwrap_or_default()
+            );
+            warn!("{}", body);
+            Logs::upload(Massage {
+                address: "host".to_string(),
+                msg_type: MsgType::REPORT,
+                body,
+            })
+            .await;
+        }
+    }
+}
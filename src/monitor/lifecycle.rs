@@ -0,0 +1,142 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// 默认超过该时长(秒)进度无变化视为卡住,可通过 monitor.stall_after 配置
+pub const STALL_AFTER: i64 = 600;
+/// 环境安装及数据集生成耗时较长,默认超过该时长(秒)无进展才视为卡住,可通过 monitor.install_stall_after 配置
+pub const INSTALL_STALL_AFTER: i64 = 3600;
+
+/// 挖矿进程所处阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum MinerState {
+    /// 环境安装、数据集生成(Generating/Map)
+    Installing,
+    Downloading,
+    Training,
+    /// 验算(eval_loss 或算力值异常大)
+    Verifying,
+    /// 任务完成,等待下一个任务
+    Idle,
+    Stalled,
+    Crashed,
+}
+
+/// 日志分析识别出的事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MinerEvent {
+    Preparing { percent: String },
+    Downloading { percent: String },
+    Progress { percent: String, hashrate: f32 },
+    Verifying,
+    Completed,
+    Failed(String),
+}
+
+impl MinerEvent {
+    pub fn state(&self) -> MinerState {
+        match self {
+            MinerEvent::Preparing { .. } => MinerState::Installing,
+            MinerEvent::Downloading { .. } => MinerState::Downloading,
+            MinerEvent::Progress { .. } => MinerState::Training,
+            MinerEvent::Verifying => MinerState::Verifying,
+            MinerEvent::Completed => MinerState::Idle,
+            MinerEvent::Failed(_) => MinerState::Crashed,
+        }
+    }
+}
+
+/// 单个地址的状态机
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lifecycle {
+    pub state: MinerState,
+    /// 进入当前状态的时间
+    pub since: DateTime<Local>,
+    /// 最近一次收到事件的时间
+    pub last_event: DateTime<Local>,
+    /// 最近一次进度变化或状态变化的时间
+    pub last_progress: DateTime<Local>,
+    pub progress: Option<String>,
+    /// 最近一次训练算力(it/s)
+    pub hashrate: Option<f32>,
+}
+
+impl Lifecycle {
+    pub fn new(now: DateTime<Local>) -> Lifecycle {
+        Lifecycle {
+            state: MinerState::Installing,
+            since: now,
+            last_event: now,
+            last_progress: now,
+            progress: None,
+            hashrate: None,
+        }
+    }
+
+    fn transit(
+        &mut self,
+        state: MinerState,
+        now: DateTime<Local>,
+    ) -> Option<(MinerState, MinerState)> {
+        if self.state == state {
+            return None;
+        }
+        let from = self.state;
+        self.state = state;
+        self.since = now;
+        self.last_progress = now;
+        Some((from, state))
+    }
+
+    /// 处理事件,状态变化时返回 (原状态, 新状态)
+    pub fn apply(
+        &mut self,
+        event: &MinerEvent,
+        now: DateTime<Local>,
+    ) -> Option<(MinerState, MinerState)> {
+        self.last_event = now;
+        let progress = match event {
+            MinerEvent::Preparing { percent } | MinerEvent::Downloading { percent } => {
+                Some(percent)
+            }
+            MinerEvent::Progress { percent, hashrate } => {
+                self.hashrate = Some(*hashrate);
+                Some(percent)
+            }
+            _ => None,
+        };
+        // 重复输出相同进度(如下载卡住)不算进展
+        if let Some(percent) = progress.filter(|percent| self.progress.as_ref() != Some(percent)) {
+            self.progress = Some(percent.clone());
+            self.last_progress = now;
+        }
+        self.transit(event.state(), now)
+    }
+
+    /// 定时检查,工作中的进程长时间无进展时转为 Stalled,安装阶段按 `install_after` 判断,
+    /// 验算阶段日志不带进度,按最近一次事件判断
+    pub fn tick(
+        &mut self,
+        now: DateTime<Local>,
+        stall_after: Duration,
+        install_after: Duration,
+    ) -> Option<(MinerState, MinerState)> {
+        let (last, after) = match self.state {
+            MinerState::Idle | MinerState::Stalled | MinerState::Crashed => return None,
+            MinerState::Installing => (self.last_progress, install_after),
+            MinerState::Verifying => (self.last_event, stall_after),
+            _ => (self.last_progress, stall_after),
+        };
+        if now - last > after {
+            self.transit(MinerState::Stalled, now)
+        } else {
+            None
+        }
+    }
+
+    /// 已发出重启,回到 Installing 重新计时,重启后仍无进展会再次转为 Stalled
+    pub fn restarted(&mut self, now: DateTime<Local>) -> Option<(MinerState, MinerState)> {
+        self.progress = None;
+        self.transit(MinerState::Installing, now)
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Arc, time::SystemTime};
+
+use super::{
+    lifecycle::MinerState,
+    nvidia::GeForce,
+    policy::RESTART_POLICY,
+    procfs::{MinerProcess, ProcScanner},
+    telemetry::GpuSample,
+    Monitor,
+};
+use crate::log::{RunLogs, Status};
+
+/// Prometheus 文本格式输出
+#[derive(Debug, Default)]
+pub struct Metrics {
+    out: String,
+}
+
+impl Metrics {
+    /// 指标说明,需在该指标的数据之前调用
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect::<Vec<String>>();
+        if labels.is_empty() {
+            let _ = writeln!(self.out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(self.out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+
+    pub fn render(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+const STATES: [MinerState; 7] = [
+    MinerState::Installing,
+    MinerState::Downloading,
+    MinerState::Training,
+    MinerState::Verifying,
+    MinerState::Idle,
+    MinerState::Stalled,
+    MinerState::Crashed,
+];
+
+/// 按地址统计任务成功/失败次数
+pub fn task_counts(logs: &RunLogs) -> BTreeMap<(String, String), u64> {
+    let mut counts = BTreeMap::new();
+    for log in logs.iter() {
+        let status = match log.status {
+            Status::Success => "success",
+            Status::Failed => "failed",
+        };
+        *counts
+            .entry((log.wallet_addr.clone(), status.to_string()))
+            .or_default() += 1;
+    }
+    counts
+}
+
+/// 显卡指标:名称、说明及从采样中取值
+type Gauge = (&'static str, &'static str, fn(&GpuSample) -> f64);
+
+/// 从 /proc 及工作目录读取的数据
+struct Scan {
+    miners: Vec<MinerProcess>,
+    /// 各地址距最后一行日志的秒数
+    log_ages: BTreeMap<String, f64>,
+    tasks: Option<RunLogs>,
+}
+
+fn scan(address: &[String]) -> Result<Scan, String> {
+    let workdir = std::env::current_dir().map_err(|e| format!("获取工作目录失败:{}", e))?;
+    let log_ages = address
+        .iter()
+        .filter_map(|address| {
+            let modified = std::fs::metadata(workdir.join("logs").join(format!("{}.txt", address)))
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+                .as_secs_f64();
+            Some((address.clone(), age))
+        })
+        .collect();
+    Ok(Scan {
+        miners: ProcScanner::default().miners().unwrap_or_default(),
+        log_ages,
+        tasks: RunLogs::load(&workdir.join("nimble-miner-public/my_logs.json")).ok(),
+    })
+}
+
+/// 采集节点指标,`monitor` 应为 `Monitor::snapshot` 的副本,读取 /proc 及文件在 `spawn_blocking` 中进行
+pub async fn collect(monitor: &Monitor) -> Result<String, String> {
+    let address = monitor.address.clone();
+    let scan = tokio::task::spawn_blocking(move || scan(&address))
+        .await
+        .map_err(|e| e.to_string())??;
+    let mut metrics = Metrics::default();
+    let nvidias = monitor.nvidias.get_normal_nvidias();
+    let assignments = monitor.assignments();
+    let gpu_of = |address: &str| {
+        assignments
+            .iter()
+            .find(|assignment| assignment.address == address)
+            .map(|assignment| assignment.gpu_id.to_string())
+            .unwrap_or_default()
+    };
+
+    metrics.family("nimble_gpus", "gauge", "识别到的显卡数量");
+    metrics.sample("nimble_gpus", &[], nvidias.len() as f64);
+    metrics.family("nimble_gpu_errors", "gauge", "识别失败的显卡数量");
+    metrics.sample(
+        "nimble_gpu_errors",
+        &[],
+        (monitor.nvidias.len() - nvidias.len()) as f64,
+    );
+
+    let samples = nvidias
+        .iter()
+        .filter_map(|nvidia| match nvidia {
+            GeForce::CARD { id, uuid, .. } => {
+                Some((id.to_string(), monitor.telemetry.latest(uuid)?))
+            }
+            GeForce::ERROR(_) => None,
+        })
+        .collect::<Vec<(String, &GpuSample)>>();
+    if !samples.is_empty() {
+        let gauges: [Gauge; 7] = [
+            (
+                "nimble_gpu_temperature_celsius",
+                "显卡温度(℃)",
+                |sample| sample.temperature as f64,
+            ),
+            ("nimble_gpu_power_watts", "显卡功耗(W)", |sample| {
+                sample.power as f64
+            }),
+            (
+                "nimble_gpu_power_limit_watts",
+                "显卡功耗上限(W)",
+                |sample| sample.power_limit as f64,
+            ),
+            (
+                "nimble_gpu_utilization_percent",
+                "SM利用率(%)",
+                |sample| sample.sm_util as f64,
+            ),
+            (
+                "nimble_gpu_memory_utilization_percent",
+                "显存读写利用率(%)",
+                |sample| sample.mem_util as f64,
+            ),
+            (
+                "nimble_gpu_memory_used_megabytes",
+                "已用显存(MB)",
+                |sample| sample.memory_used as f64,
+            ),
+            (
+                "nimble_gpu_throttled",
+                "是否因功耗或温度降频",
+                |sample| {
+                    if sample.throttling().next().is_some() {
+                        1f64
+                    } else {
+                        0f64
+                    }
+                },
+            ),
+        ];
+        for (name, help, value) in gauges {
+            metrics.family(name, "gauge", help);
+            for (gpu, sample) in samples.iter() {
+                metrics.sample(name, &[("gpu", gpu.as_str())], value(sample));
+            }
+        }
+        metrics.family("nimble_gpu_ecc_errors", "gauge", "未纠正的ECC错误数");
+        for (gpu, sample) in samples.iter() {
+            if let Some(ecc_errors) = sample.ecc_errors {
+                let labels = [("gpu", gpu.as_str())];
+                metrics.sample("nimble_gpu_ecc_errors", &labels, ecc_errors as f64);
+            }
+        }
+    }
+
+    metrics.family("nimble_miner_up", "gauge", "挖矿进程是否在运行");
+    for address in monitor.address.iter() {
+        let up = scan.miners.iter().any(|miner| &miner.address == address);
+        let gpu = gpu_of(address);
+        let labels = [("address", address.as_str()), ("gpu", gpu.as_str())];
+        metrics.sample("nimble_miner_up", &labels, if up { 1f64 } else { 0f64 });
+    }
+
+    metrics.family("nimble_hashrate", "gauge", "最近一次训练算力(it/s)");
+    for (address, lifecycle) in monitor.lifecycles.iter() {
+        if let Some(hashrate) = lifecycle.hashrate {
+            let gpu = gpu_of(address);
+            let labels = [("address", address.as_str()), ("gpu", gpu.as_str())];
+            metrics.sample("nimble_hashrate", &labels, hashrate as f64);
+        }
+    }
+
+    metrics.family("nimble_miner_state", "gauge", "挖矿进程当前阶段");
+    for (address, lifecycle) in monitor.lifecycles.iter() {
+        for state in STATES.iter() {
+            let value = if lifecycle.state == *state {
+                1f64
+            } else {
+                0f64
+            };
+            let state = state.to_string();
+            let labels = [("address", address.as_str()), ("state", state.as_str())];
+            metrics.sample("nimble_miner_state", &labels, value);
+        }
+    }
+
+    metrics.family("nimble_log_age_seconds", "gauge", "距最后一行日志的秒数");
+    for (address, age) in scan.log_ages.iter() {
+        metrics.sample("nimble_log_age_seconds", &[("address", address)], *age);
+    }
+
+    let restarts = {
+        let policy = Arc::clone(&RESTART_POLICY);
+        let policy_locked = policy.lock().await;
+        (*policy_locked).restarts_total().clone()
+    };
+    metrics.family(
+        "nimble_miner_restarts_total",
+        "counter",
+        "日志触发的重启次数",
+    );
+    for address in monitor.address.iter() {
+        let total = restarts.get(address).copied().unwrap_or_default();
+        metrics.sample(
+            "nimble_miner_restarts_total",
+            &[("address", address)],
+            total as f64,
+        );
+    }
+
+    if let Some(logs) = scan.tasks {
+        metrics.family("nimble_tasks_total", "counter", "已完成的任务数");
+        for ((address, status), count) in task_counts(&logs) {
+            let labels = [("address", address.as_str()), ("status", status.as_str())];
+            metrics.sample("nimble_tasks_total", &labels, count as f64);
+        }
+    }
+    Ok(metrics.render())
+}
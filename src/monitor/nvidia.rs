@@ -1,101 +1,248 @@
 use std::{
     ops::{Deref, DerefMut},
+    path::PathBuf,
     process::Command,
-    str::FromStr,
 };
 
+use nvml_wrapper::{cuda_driver_version_major, cuda_driver_version_minor, error::NvmlError, Nvml};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 use crate::server::clore::model::CardType;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum GeForce {
     CARD {
         id: u32,
         uuid: String,
         card_type: CardType,
+        /// 显卡名称,如 NVIDIA GeForce RTX 4090
+        #[serde(default)]
+        name: String,
+        /// 显存(MB),通过 nvidia-smi 获取时为空
+        #[serde(default)]
+        memory: Option<u64>,
+        /// PCIe 总线地址,通过 nvidia-smi 获取时为空
+        #[serde(default)]
+        bus_id: Option<String>,
     },
     ERROR(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GeForces(Vec<GeForce>);
+/// 显卡驱动信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Driver {
+    /// 驱动版本,如 550.54.14
+    pub version: String,
+    /// 驱动支持的最高 CUDA 版本,如 12.4
+    pub cuda: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeForces {
+    cards: Vec<GeForce>,
+    /// 通过 nvidia-smi 获取时为空
+    pub driver: Option<Driver>,
+}
 
 impl Deref for GeForces {
     type Target = Vec<GeForce>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.cards
     }
 }
 
 impl DerefMut for GeForces {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.cards
     }
 }
 
-impl GeForces {
-    pub fn new() -> GeForces {
-        let output = GeForces::command();
-        let nvidias = match output {
-            Ok(nvidia_info) => nvidia_info
-                .split("\n")
-                .map(|nvidia| nvidia.trim())
-                .filter(|nvidia| !nvidia.is_empty())
-                .map(|nvidia| {
-                    let card = nvidia
-                        .split(" ")
-                        .map(|s| {
-                            s.trim()
-                                .trim_matches(|c| c == ':' || c == '(' || c == '\\')
-                                .to_string()
-                        })
-                        .collect::<Vec<String>>();
-                    info!("{:?}", card);
-                    match &card[..] {
-                        [_, id, card, _, _, card_type, _flag1, _flag2, _, uuid, ..] => {
-                            let card_type = format!("{}{}S", card, card_type);
-                            let geforce = GeForce::CARD {
-                                id: id.parse::<u32>().unwrap_or_default(),
-                                uuid: uuid.clone(),
-                                card_type: CardType::from_str(&card_type).unwrap(),
-                            };
-                            geforce
-                        }
-                        [_, id, card, _, _, card_type, _flag1, _, uuid, ..] => {
-                            let card_type = format!("{}{}TI", card, card_type);
-                            let geforce = GeForce::CARD {
-                                id: id.parse::<u32>().unwrap_or_default(),
-                                uuid: uuid.clone(),
-                                card_type: CardType::from_str(&card_type).unwrap(),
-                            };
-                            geforce
-                        }
-                        [_, id, card, _, _, card_type, _, uuid, ..] => {
-                            let card_type = format!("{}{}", card, card_type);
-                            let geforce = GeForce::CARD {
-                                id: id.parse::<u32>().unwrap_or_default(),
-                                uuid: uuid.clone(),
-                                card_type: CardType::from_str(&card_type).unwrap(),
-                            };
-                            geforce
-                        }
-                        _ => {
-                            let e = format!("识别显卡错误:{:?}", card);
-                            warn!(e);
-                            GeForce::ERROR(e)
-                        }
-                    }
-                })
-                .collect::<Vec<GeForce>>(),
-            Err(e) => {
-                error!("获取显卡信息失败:{:?}", e);
-                Vec::new()
+/// 显卡信息来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuBackendKind {
+    /// 优先 NVML,不可用时解析 nvidia-smi
+    #[default]
+    Auto,
+    Nvml,
+    /// 解析 `nvidia-smi -L` 输出
+    Smi,
+    /// 读取录制的显卡信息文件,用于测试
+    Fixture,
+}
+
+/// 识别本机显卡,会调用外部命令或驱动,需在 `spawn_blocking` 中运行
+pub trait GpuBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn discover(&self) -> Result<GeForces, String>;
+}
+
+pub fn new(config: &crate::config::Gpu) -> Box<dyn GpuBackend> {
+    match config.backend {
+        GpuBackendKind::Auto => Box::new(AutoBackend),
+        GpuBackendKind::Nvml => Box::new(NvmlBackend),
+        GpuBackendKind::Smi => Box::new(SmiBackend),
+        GpuBackendKind::Fixture => Box::new(FixtureBackend::new(
+            config.fixture.clone().unwrap_or_default(),
+        )),
+    }
+}
+
+pub struct AutoBackend;
+
+impl GpuBackend for AutoBackend {
+    fn name(&self) -> &'static str {
+        "auto"
+    }
+
+    fn discover(&self) -> Result<GeForces, String> {
+        NvmlBackend.discover().or_else(|e| {
+            warn!("NVML不可用,使用nvidia-smi获取显卡信息:{}", e);
+            SmiBackend.discover()
+        })
+    }
+}
+
+pub struct NvmlBackend;
+
+impl NvmlBackend {
+    fn device(nvml: &Nvml, index: u32) -> Result<GeForce, NvmlError> {
+        let device = nvml.device_by_index(index)?;
+        let name = device.name()?;
+        Ok(GeForce::CARD {
+            id: device.index()?,
+            uuid: device.uuid()?,
+            card_type: CardType::from_name(&name),
+            memory: Some(device.memory_info()?.total / MB),
+            bus_id: Some(device.pci_info()?.bus_id),
+            name,
+        })
+    }
+}
+
+impl GpuBackend for NvmlBackend {
+    fn name(&self) -> &'static str {
+        "nvml"
+    }
+
+    fn discover(&self) -> Result<GeForces, String> {
+        let nvml = Nvml::init().map_err(|e| e.to_string())?;
+        let count = nvml.device_count().map_err(|e| e.to_string())?;
+        let cards = (0..count)
+            .map(|index| match NvmlBackend::device(&nvml, index) {
+                Ok(card) => card,
+                Err(e) => {
+                    let e = format!("读取显卡{}信息失败:{}", index, e);
+                    warn!(e);
+                    GeForce::ERROR(e)
+                }
+            })
+            .collect::<Vec<GeForce>>();
+        let driver = match (nvml.sys_driver_version(), nvml.sys_cuda_driver_version()) {
+            (Ok(version), Ok(cuda)) => Some(Driver {
+                version,
+                cuda: format!(
+                    "{}.{}",
+                    cuda_driver_version_major(cuda),
+                    cuda_driver_version_minor(cuda)
+                ),
+            }),
+            (version, cuda) => {
+                warn!("获取驱动版本失败:{:?},{:?}", version.err(), cuda.err());
+                None
             }
         };
-        GeForces(nvidias)
+        info!("显卡信息:{:?},驱动:{:?}", cards, driver);
+        Ok(GeForces { cards, driver })
+    }
+}
+
+pub struct SmiBackend;
+
+impl GpuBackend for SmiBackend {
+    fn name(&self) -> &'static str {
+        "smi"
+    }
+
+    fn discover(&self) -> Result<GeForces, String> {
+        let output = Command::new("nvidia-smi")
+            .arg("-L")
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(format!("nvidia-smi执行失败:{}", stderr));
+        }
+        let nvidia_info = String::from_utf8(output.stdout).map_err(|e| e.to_string())?;
+        Ok(GeForces::parse(&nvidia_info))
+    }
+}
+
+/// 录制的显卡信息:.json 为 `GeForces` 序列化结果,其余按 `nvidia-smi -L` 输出解析
+pub struct FixtureBackend {
+    path: PathBuf,
+}
+
+impl FixtureBackend {
+    pub fn new(path: PathBuf) -> FixtureBackend {
+        FixtureBackend { path }
+    }
+}
+
+impl GpuBackend for FixtureBackend {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn discover(&self) -> Result<GeForces, String> {
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        if self.path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str::<GeForces>(&content)
+                .map_err(|e| format!("{}: {}", self.path.display(), e))
+        } else {
+            Ok(GeForces::parse(&content))
+        }
+    }
+}
+
+impl GeForces {
+    /// 解析 `nvidia-smi -L` 输出,每行如:
+    /// `GPU 0: NVIDIA GeForce RTX 4070 Ti (UUID: GPU-5e4c623f-998d-912c-3743-3465506f63ad)`
+    pub fn parse(output: &str) -> GeForces {
+        let regex = Regex::new(r"^GPU (\d+): (.+) \(UUID: ([\w-]+)\)$").unwrap();
+        let cards = output
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| match regex.captures(line) {
+                Some(captures) => {
+                    let (_, [id, name, uuid]) = captures.extract::<3>();
+                    GeForce::CARD {
+                        id: id.parse::<u32>().unwrap_or_default(),
+                        uuid: uuid.to_string(),
+                        card_type: CardType::from_name(name),
+                        name: name.to_string(),
+                        memory: None,
+                        bus_id: None,
+                    }
+                }
+                None => {
+                    let e = format!("识别显卡错误:{}", line);
+                    warn!(e);
+                    GeForce::ERROR(e)
+                }
+            })
+            .collect();
+        GeForces {
+            cards,
+            driver: None,
+        }
     }
 
     pub fn get_normal_nvidias(&self) -> Vec<GeForce> {
@@ -111,22 +258,4 @@ impl GeForces {
 
         nvidias
     }
-
-    fn command() -> Result<String, String> {
-        //         let output = r"
-        // GPU 0: NVIDIA GeForce RTX 4070 (UUID: GPU-5e4c623f-998d-912c-3743-3465506f63ad)
-        // GPU 1: NVIDIA GeForce RTX 4070 Ti (UUID: GPU-5e4c623f-998d-912c-3743-3465506f63ad)
-        // GPU 2: NVIDIA GeForce RTX 4070 Ti SUPER (UUID: GPU-5e4c623f-998d-912c-3743-3465506f63ad)
-        // GPU 4: NVIDIA GeForce RTX 4090 (UUID: GPU-13d44c72-a798-c126-54cb-98e543beadd3)
-        //         ";
-        // return Ok(output.to_string());
-        let output = Command::new("nvidia-smi")
-            .arg("-L")
-            .output()
-            .map_err(|e| e.to_string())?
-            .stdout;
-        let nvidia_info = String::from_utf8(output).map_err(|e| e.to_string())?;
-
-        Ok(nvidia_info)
-    }
 }
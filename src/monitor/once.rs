@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use tracing::{error, info};
+
+use super::{
+    nvidia::GeForce,
+    procfs::MinerProcess,
+    reconcile::{Plan, Step},
+    supervisor::{self, DryRun, Supervisor, SupervisorKind},
+    Monitor, MONITOR,
+};
+
+/// 单次检查的结果,即 `--once`/`--dry-run` 的退出码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 服务正常,无需处理
+    Healthy = 0,
+    /// 检查或处理失败
+    Failed = 1,
+    /// 已处理,dry-run 时为需要处理
+    Changed = 2,
+    /// 配置加载失败
+    InvalidConfig = 3,
+}
+
+/// 运行一次检查并处理,`dry_run` 时只输出检测结果和将执行的操作
+pub async fn once(dry_run: bool) -> Outcome {
+    let kind = Monitor::get_config().await.supervisor;
+    // native 后端的挖矿进程由常驻的本程序守护,处理后立即退出会留下无人守护的进程
+    if kind == SupervisorKind::Native && !dry_run {
+        error!("native 后端不支持 --once,请使用 --dry-run 或常驻运行");
+        return Outcome::Failed;
+    }
+    let nvidias = match Monitor::detect_gpus().await {
+        Ok(nvidias) => nvidias,
+        Err(e) => {
+            error!("获取显卡信息失败:{}", e);
+            return Outcome::Failed;
+        }
+    };
+    let miners = Monitor::py_pros().await.unwrap_or_default();
+    let monitor = Arc::clone(&MONITOR);
+    let mut monitor_locked = monitor.lock().await;
+    monitor_locked.nvidias = nvidias;
+    let plan = if dry_run {
+        monitor_locked.plan(&miners).await
+    } else {
+        (*monitor_locked).prepare(&miners).await
+    };
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("调用程序失败:{}", e);
+            return Outcome::Failed;
+        }
+    };
+    let address = (*monitor_locked).address.clone();
+    let nvidias = monitor_locked.nvidias.to_vec();
+    let driver = monitor_locked.nvidias.driver.clone();
+    drop(monitor_locked);
+
+    let healthy = plan.is_healthy();
+    let result = tokio::task::spawn_blocking(move || {
+        if dry_run {
+            let supervisor = DryRun::new(supervisor::inspect(kind));
+            if let Some(driver) = driver {
+                println!("驱动:{},CUDA:{}", driver.version, driver.cuda);
+            }
+            for line in report(&supervisor, &address, &nvidias, &miners, &plan) {
+                println!("{}", line);
+            }
+            Ok(())
+        } else if healthy {
+            Ok(())
+        } else {
+            Monitor::apply(supervisor::new(kind).as_ref(), &plan)
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    match result {
+        Ok(()) if healthy => {
+            info!("服务正常!!");
+            Outcome::Healthy
+        }
+        Ok(()) => Outcome::Changed,
+        Err(e) => {
+            error!("处理挖矿进程失败:{}", e);
+            Outcome::Failed
+        }
+    }
+}
+
+/// dry-run 输出:检测到的显卡、地址、服务和进程,以及每张显卡将执行的操作和原因
+pub fn report(
+    supervisor: &DryRun,
+    address: &[String],
+    nvidias: &[GeForce],
+    miners: &[MinerProcess],
+    plan: &Plan,
+) -> Vec<String> {
+    let mut lines = vec!["显卡:".to_string()];
+    for nvidia in nvidias.iter() {
+        match nvidia {
+            GeForce::CARD {
+                id,
+                uuid,
+                card_type,
+                name,
+                memory,
+                bus_id,
+            } => {
+                let mut line = format!("  {} {} {}({})", id, uuid, name, card_type);
+                if let Some(memory) = memory {
+                    line.push_str(&format!(" {}MB", memory));
+                }
+                if let Some(bus_id) = bus_id {
+                    line.push_str(&format!(" {}", bus_id));
+                }
+                lines.push(line);
+            }
+            GeForce::ERROR(e) => lines.push(format!("  {}", e)),
+        }
+    }
+
+    lines.push("地址:".to_string());
+    let assigned = plan
+        .gpus
+        .iter()
+        .filter(|gpu| gpu.address.is_some())
+        .collect::<Vec<_>>();
+    for addr in address.iter() {
+        match assigned
+            .iter()
+            .find(|gpu| gpu.address.as_ref() == Some(addr))
+        {
+            Some(gpu) => lines.push(format!("  {} -> 显卡{}", addr, gpu.gpu_id)),
+            None => lines.push(format!("  {} 未分配显卡", addr)),
+        }
+    }
+
+    lines.push(format!("服务({}):", supervisor.name()));
+    for gpu in assigned.iter() {
+        let status = supervisor
+            .status(gpu.gpu_id)
+            .map(|status| format!("{:?}", status))
+            .unwrap_or_else(|e| format!("查询失败:{}", e));
+        lines.push(format!(
+            "  {} {}",
+            supervisor::service_name(gpu.gpu_id),
+            status
+        ));
+    }
+
+    lines.push("挖矿进程:".to_string());
+    for miner in miners.iter() {
+        lines.push(format!(
+            "  pid:{} 地址:{} CUDA_VISIBLE_DEVICES:{} pm2:{}",
+            miner.pid,
+            miner.address,
+            miner.cuda_visible_devices.as_deref().unwrap_or("-"),
+            miner
+                .pm2_id
+                .map(|id| id.to_string())
+                .unwrap_or("-".to_string())
+        ));
+    }
+
+    lines.push("处理计划:".to_string());
+    for miner in plan.stray.iter() {
+        lines.push(format!(
+            "  进程{}({})的显卡未知,先停止:{:?}",
+            miner.pid, miner.address, miner.cuda_visible_devices
+        ));
+        let result = Monitor::stop(supervisor, miner.pid, miner.pm2_id);
+        for action in supervisor.take() {
+            lines.push(format!("    -> {}", action));
+        }
+        if let Err(e) = result {
+            lines.push(format!("    -> 失败:{}", e));
+        }
+    }
+    for gpu in plan.gpus.iter() {
+        let address = gpu.address.as_deref().unwrap_or("未分配地址");
+        if gpu.steps.is_empty() {
+            lines.push(format!(
+                "  显卡{}({}) {}:无需处理",
+                gpu.gpu_id, gpu.uuid, address
+            ));
+            continue;
+        }
+        lines.push(format!("  显卡{}({}) {}:", gpu.gpu_id, gpu.uuid, address));
+        for step in gpu.steps.iter() {
+            match step {
+                Step::Stop {
+                    pid,
+                    address,
+                    reason,
+                    ..
+                } => lines.push(format!("    停止进程{}({}):{}", pid, address, reason)),
+                Step::Start { address } => lines.push(format!("    显卡空闲,拉起地址:{}", address)),
+            }
+        }
+        let result = Monitor::reconcile(supervisor, gpu);
+        for action in supervisor.take() {
+            lines.push(format!("    -> {}", action));
+        }
+        if let Err(e) = result {
+            lines.push(format!("    -> 失败:{}", e));
+        }
+    }
+    if plan.is_healthy() {
+        lines.push("服务正常,无需处理".to_string());
+    }
+    lines
+}
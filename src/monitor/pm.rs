@@ -32,6 +32,7 @@ pub struct Pros {
 pub enum Action {
     START,
     RESTART,
+    STOP,
     SKIP,
 }
 
@@ -77,6 +78,12 @@ impl Process {
         action
     }
 
+    pub fn get_name(&self, pm_id: u32) -> Option<String> {
+        self.iter()
+            .find(|pros| pros.pm_id == pm_id)
+            .map(|pros| pros.name.clone())
+    }
+
     pub fn to_pm2(&self) -> Vec<Pm2> {
         let mut pm2 = Vec::<Pm2>::new();
         for pros in (*self).iter() {
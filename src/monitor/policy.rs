@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Duration, Local};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::Restart;
+
+lazy_static! {
+    pub static ref RESTART_POLICY: Arc<Mutex<RestartPolicy>> =
+        Arc::new(Mutex::new(RestartPolicy::default()));
+}
+
+/// 保留的决策记录数量
+const MAX_RECORDS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Decision {
+    Restart,
+    /// 距上次重启不足冷却时间,忽略
+    Cooldown {
+        remaining: i64,
+    },
+    /// 重启次数过多,上报并停止
+    Escalate,
+    /// 已升级处理,暂停期内忽略
+    Suppressed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub time: DateTime<Local>,
+    pub address: String,
+    pub reason: String,
+    pub decision: Decision,
+}
+
+/// 按地址记录重启历史并决定如何处理重启请求
+#[derive(Debug, Default)]
+pub struct RestartPolicy {
+    restarts: HashMap<String, Vec<DateTime<Local>>>,
+    escalated: HashMap<String, DateTime<Local>>,
+    records: VecDeque<Record>,
+    /// 各地址累计重启次数
+    restarts_total: HashMap<String, u64>,
+}
+
+impl RestartPolicy {
+    pub fn decide(
+        &mut self,
+        config: &Restart,
+        address: &str,
+        reason: &str,
+        now: DateTime<Local>,
+    ) -> Decision {
+        let hour = Duration::hours(1);
+        let decision = if self.is_escalated(address, now) {
+            Decision::Suppressed
+        } else {
+            self.escalated.remove(address);
+            let restarts = self.restarts.entry(address.to_string()).or_default();
+            restarts.retain(|time| now - *time < hour);
+            let cooldown = Duration::seconds(config.cooldown);
+            match restarts.last() {
+                Some(last) if now - *last < cooldown => Decision::Cooldown {
+                    remaining: (cooldown - (now - *last)).num_seconds(),
+                },
+                _ if restarts.len() >= config.max_per_hour => {
+                    restarts.clear();
+                    self.escalated.insert(address.to_string(), now);
+                    Decision::Escalate
+                }
+                _ => {
+                    restarts.push(now);
+                    *self.restarts_total.entry(address.to_string()).or_default() += 1;
+                    Decision::Restart
+                }
+            }
+        };
+        if self.records.len() >= MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(Record {
+            time: now,
+            address: address.to_string(),
+            reason: reason.to_string(),
+            decision: decision.clone(),
+        });
+        decision
+    }
+
+    /// 升级处理后一小时内不再自动重启
+    pub fn is_escalated(&self, address: &str, now: DateTime<Local>) -> bool {
+        self.escalated
+            .get(address)
+            .map(|time| now - *time < Duration::hours(1))
+            .unwrap_or(false)
+    }
+
+    pub fn restarts_total(&self) -> &HashMap<String, u64> {
+        &self.restarts_total
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.records.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// 内核时钟频率,/proc/[pid]/stat 中的启动时间以此为单位,Linux 上固定为 100
+const CLK_TCK: u64 = 100;
+/// 向上查找 pm2 进程id的最大层数
+const MAX_DEPTH: usize = 8;
+
+/// /proc 下单个进程的信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    /// 进程组id
+    pub pgrp: u32,
+    /// 自系统启动起的时钟周期数
+    pub start_ticks: u64,
+    pub cmdline: Vec<String>,
+    pub environ: HashMap<String, String>,
+}
+
+/// 挖矿进程(execute.py)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinerProcess {
+    pub pid: u32,
+    pub start_time: Option<DateTime<Local>>,
+    pub address: String,
+    pub cuda_visible_devices: Option<String>,
+    /// 所属的 pm2 进程id,非 pm2 拉起时为 None
+    pub pm2_id: Option<u32>,
+}
+
+/// 读取 /proc 的进程扫描器,根目录可替换为测试目录
+#[derive(Debug, Clone)]
+pub struct ProcScanner {
+    root: PathBuf,
+}
+
+impl Default for ProcScanner {
+    fn default() -> Self {
+        ProcScanner::new("/proc")
+    }
+}
+
+impl ProcScanner {
+    pub fn new(root: impl Into<PathBuf>) -> ProcScanner {
+        ProcScanner { root: root.into() }
+    }
+
+    fn read_nul(path: &Path) -> Vec<String> {
+        fs::read(path)
+            .map(|bytes| {
+                bytes
+                    .split(|b| *b == 0)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| String::from_utf8_lossy(s).to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 读取单个进程,进程已退出或无权限时返回 None
+    pub fn process(&self, pid: u32) -> Option<ProcInfo> {
+        let dir = self.root.join(pid.to_string());
+        let stat = fs::read_to_string(dir.join("stat")).ok()?;
+        // 进程名可能包含空格和括号,从最后一个 ')' 之后开始解析
+        let fields = stat
+            .get(stat.rfind(')')? + 1..)?
+            .split_whitespace()
+            .collect::<Vec<&str>>();
+        let ppid = fields.get(1)?.parse::<u32>().ok()?;
+        let pgrp = fields.get(2)?.parse::<u32>().ok()?;
+        let start_ticks = fields.get(19)?.parse::<u64>().ok()?;
+        let environ = ProcScanner::read_nul(&dir.join("environ"))
+            .into_iter()
+            .filter_map(|item| {
+                item.split_once('=')
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+            })
+            .collect();
+        Some(ProcInfo {
+            pid,
+            ppid,
+            pgrp,
+            start_ticks,
+            cmdline: ProcScanner::read_nul(&dir.join("cmdline")),
+            environ,
+        })
+    }
+
+    /// 全部进程,按pid排序
+    pub fn processes(&self) -> Result<Vec<ProcInfo>, String> {
+        let mut pids = fs::read_dir(&self.root)
+            .map_err(|e| format!("{}: {}", self.root.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .collect::<Vec<u32>>();
+        pids.sort();
+        Ok(pids
+            .into_iter()
+            .filter_map(|pid| self.process(pid))
+            .collect())
+    }
+
+    /// 系统启动时间(秒级时间戳),取自 /proc/stat 的 btime
+    fn boot_time(&self) -> Option<u64> {
+        fs::read_to_string(self.root.join("stat"))
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("btime "))
+            .and_then(|btime| btime.trim().parse::<u64>().ok())
+    }
+
+    /// 进程自身或最近的祖先进程环境变量中的 pm_id
+    fn pm2_id(&self, process: &ProcInfo) -> Option<u32> {
+        let mut current = Some(process.clone());
+        for _ in 0..MAX_DEPTH {
+            let process = current?;
+            if let Some(pm_id) = process.environ.get("pm_id") {
+                return pm_id.parse::<u32>().ok();
+            }
+            if process.ppid == 0 {
+                return None;
+            }
+            current = self.process(process.ppid);
+        }
+        None
+    }
+
+    /// 扫描 execute.py 挖矿进程,命令行中找不到地址的进程跳过
+    pub fn miners(&self) -> Result<Vec<MinerProcess>, String> {
+        let reg = regex::Regex::new(r"^(nimble[\w]+)$").map_err(|e| e.to_string())?;
+        let boot_time = self.boot_time();
+        let mut miners = Vec::new();
+        for process in self.processes()? {
+            let script = process
+                .cmdline
+                .iter()
+                .position(|arg| arg == "execute.py" || arg.ends_with("/execute.py"));
+            let Some(script) = script else {
+                continue;
+            };
+            let address = process.cmdline[script + 1..]
+                .iter()
+                .find(|arg| reg.is_match(arg));
+            let Some(address) = address else {
+                warn!(
+                    "挖矿进程{}命令行中无地址:{:?}",
+                    process.pid, process.cmdline
+                );
+                continue;
+            };
+            let start_time = boot_time.and_then(|boot_time| {
+                let secs = boot_time + process.start_ticks / CLK_TCK;
+                Local.timestamp_opt(secs as i64, 0).single()
+            });
+            miners.push(MinerProcess {
+                pid: process.pid,
+                start_time,
+                address: address.clone(),
+                cuda_visible_devices: process.environ.get("CUDA_VISIBLE_DEVICES").cloned(),
+                pm2_id: self.pm2_id(&process),
+            });
+        }
+        Ok(miners)
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::nvidia::GeForce;
+use super::procfs::MinerProcess;
+
+/// 地址与显卡的对应关系
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub address: String,
+    pub gpu_id: u32,
+    pub uuid: String,
+}
+
+/// 单张显卡需要执行的操作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Step {
+    /// 停止进程:地址不属于该显卡或重复运行
+    Stop {
+        pid: u32,
+        pm2_id: Option<u32>,
+        address: String,
+        reason: String,
+    },
+    /// 显卡空闲,启动分配的地址
+    Start { address: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuPlan {
+    pub gpu_id: u32,
+    pub uuid: String,
+    /// 分配给该显卡的地址,地址少于显卡时为 None
+    pub address: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Plan {
+    pub gpus: Vec<GpuPlan>,
+    /// 无法确定运行在哪张显卡上的进程,全部停止
+    pub stray: Vec<MinerProcess>,
+}
+
+impl Plan {
+    pub fn is_healthy(&self) -> bool {
+        self.stray.is_empty() && self.gpus.iter().all(|gpu| gpu.steps.is_empty())
+    }
+}
+
+/// 分配地址到显卡:已绑定的地址(`bound`,地址 -> 显卡UUID)使用绑定的显卡,
+/// 绑定的显卡未识别到时不分配,避免挖矿进程被迁移到其他显卡;
+/// 其余地址按显卡id从小到大分配空闲显卡,显卡id可能不连续(如 0,1,2,4)
+pub fn assign(
+    address: &[String],
+    nvidias: &[GeForce],
+    bound: &BTreeMap<String, String>,
+) -> Vec<Assignment> {
+    let mut cards = nvidias
+        .iter()
+        .filter_map(|nvidia| match nvidia {
+            GeForce::CARD { id, uuid, .. } => Some((*id, uuid.clone())),
+            GeForce::ERROR(_) => None,
+        })
+        .collect::<Vec<(u32, String)>>();
+    cards.sort();
+    let mut assignments = Vec::new();
+    let mut unbound = Vec::new();
+    for address in address.iter() {
+        let Some(uuid) = bound.get(address) else {
+            unbound.push(address);
+            continue;
+        };
+        match cards.iter().find(|(_, card)| card == uuid) {
+            Some((gpu_id, uuid)) => assignments.push(Assignment {
+                address: address.clone(),
+                gpu_id: *gpu_id,
+                uuid: uuid.clone(),
+            }),
+            None => warn!("地址{}绑定的显卡{}未识别到,暂不分配", address, uuid),
+        }
+    }
+    // 已被其他地址绑定的显卡不再分配
+    let free = cards
+        .into_iter()
+        .filter(|(_, uuid)| {
+            !address
+                .iter()
+                .any(|address| bound.get(address) == Some(uuid))
+        })
+        .collect::<Vec<(u32, String)>>();
+    if unbound.len() > free.len() {
+        warn!(
+            "地址数量多于空闲显卡数量({}),未分配的地址:{}",
+            free.len(),
+            unbound[free.len()..]
+                .iter()
+                .map(|address| address.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        );
+    }
+    assignments.extend(
+        unbound
+            .into_iter()
+            .zip(free)
+            .map(|(address, (gpu_id, uuid))| Assignment {
+                address: address.clone(),
+                gpu_id,
+                uuid,
+            }),
+    );
+    assignments.sort_by_key(|assignment| assignment.gpu_id);
+    assignments
+}
+
+/// 进程 CUDA_VISIBLE_DEVICES 对应的显卡id,支持序号和 GPU-uuid 两种写法
+fn device_of(miner: &MinerProcess, nvidias: &[GeForce]) -> Option<u32> {
+    let device = miner
+        .cuda_visible_devices
+        .as_ref()?
+        .split(',')
+        .next()?
+        .trim();
+    nvidias.iter().find_map(|nvidia| match nvidia {
+        GeForce::CARD { id, uuid, .. } if id.to_string() == device || uuid == device => Some(*id),
+        _ => None,
+    })
+}
+
+/// 对比地址分配与实际运行的进程,得出每张显卡的处理计划。
+/// `nvidias` 为完整的显卡列表(含 `GeForce::ERROR`),列表为空或有显卡读取失败时
+/// 无法确认进程所在显卡已不存在,不停止这些进程
+pub fn plan(assignments: &[Assignment], nvidias: &[GeForce], miners: &[MinerProcess]) -> Plan {
+    let mut plan = Plan::default();
+    let mut placed = Vec::new();
+    let complete = nvidias
+        .iter()
+        .any(|nvidia| matches!(nvidia, GeForce::CARD { .. }))
+        && !nvidias
+            .iter()
+            .any(|nvidia| matches!(nvidia, GeForce::ERROR(_)));
+    for miner in miners.iter() {
+        match device_of(miner, nvidias) {
+            Some(gpu_id) => placed.push((gpu_id, miner)),
+            None if complete => {
+                warn!(
+                    "挖矿进程{}的显卡未知:{:?}",
+                    miner.pid, miner.cuda_visible_devices
+                );
+                plan.stray.push(miner.clone());
+            }
+            None => {
+                warn!(
+                    "显卡信息不完整,暂不处理挖矿进程{}:{:?}",
+                    miner.pid, miner.cuda_visible_devices
+                );
+            }
+        }
+    }
+
+    for nvidia in nvidias.iter() {
+        let GeForce::CARD { id, uuid, .. } = nvidia else {
+            continue;
+        };
+        let address = assignments
+            .iter()
+            .find(|assignment| assignment.gpu_id == *id)
+            .map(|assignment| assignment.address.clone());
+        let mut steps = Vec::new();
+        let mut running = false;
+        for (_, miner) in placed.iter().filter(|(gpu_id, _)| gpu_id == id) {
+            let reason = if Some(&miner.address) != address.as_ref() {
+                match assignments.iter().find(|a| a.address == miner.address) {
+                    Some(assignment) => format!("应运行在显卡{}上", assignment.gpu_id),
+                    None => "地址未分配到该显卡".to_string(),
+                }
+            } else if running {
+                "同一显卡上重复运行".to_string()
+            } else {
+                running = true;
+                continue;
+            };
+            steps.push(Step::Stop {
+                pid: miner.pid,
+                pm2_id: miner.pm2_id,
+                address: miner.address.clone(),
+                reason,
+            });
+        }
+        if let (Some(address), false) = (&address, running) {
+            steps.push(Step::Start {
+                address: address.clone(),
+            });
+        }
+        plan.gpus.push(GpuPlan {
+            gpu_id: *id,
+            uuid: uuid.clone(),
+            address,
+            steps,
+        });
+    }
+    plan
+}
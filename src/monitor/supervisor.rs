@@ -0,0 +1,386 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::pm::{self, Action};
+
+pub mod native;
+
+/// 挖矿进程管理后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SupervisorKind {
+    /// 通过 execute.sh 调用 pm2,需要安装 Node.js
+    #[default]
+    Pm2,
+    /// systemd 用户服务,每张显卡一个 nimble{显卡序号}.service
+    Systemd,
+    /// 由本程序直接启动并守护挖矿进程,不依赖 pm2
+    Native,
+}
+
+/// 挖矿服务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    Online,
+    Stopped,
+    /// 短时间内反复崩溃,暂停拉起中,调和时不处理
+    CrashLoop,
+    /// 服务不存在
+    Missing,
+}
+
+/// 按显卡管理挖矿服务,服务名为 `nimble{显卡序号}`
+pub trait Supervisor: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn start(&self, card_number: u32, address: &str) -> Result<(), String>;
+    fn restart(&self, card_number: u32) -> Result<(), String>;
+    /// 停止并删除服务
+    fn stop(&self, card_number: u32) -> Result<(), String>;
+    fn status(&self, card_number: u32) -> Result<Status, String>;
+    /// 进程所属服务的显卡序号,不归该后端管理时返回 None
+    fn owner(&self, pid: u32, pm2_id: Option<u32>) -> Option<u32>;
+    /// 结束不归该后端管理的进程
+    fn kill(&self, pid: u32) -> Result<(), String> {
+        let output = Command::new("kill")
+            .arg(pid.to_string())
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| e.to_string())?;
+        check_output("kill", &output)?;
+        Ok(())
+    }
+}
+
+/// 命令退出码非零时返回 stderr
+fn check_output(command: &str, output: &Output) -> Result<(), String> {
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "{}执行失败,{}:{}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// 地址会拼接进 shell 命令,启动前校验
+fn check_address(address: &str) -> Result<(), String> {
+    if crate::config::check::is_nimble_address(address) {
+        Ok(())
+    } else {
+        Err(format!("挖矿地址不合法:{:?}", address))
+    }
+}
+
+/// 只记录将要执行的操作,查询类调用交给实际后端,用于 `--dry-run`
+pub struct DryRun {
+    inner: Box<dyn Supervisor>,
+    actions: Mutex<Vec<String>>,
+}
+
+impl DryRun {
+    pub fn new(inner: Box<dyn Supervisor>) -> DryRun {
+        DryRun {
+            inner,
+            actions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 取出已记录的操作
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+
+    fn record(&self, action: String) -> Result<(), String> {
+        self.actions.lock().unwrap().push(action);
+        Ok(())
+    }
+}
+
+impl Supervisor for DryRun {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn start(&self, card_number: u32, address: &str) -> Result<(), String> {
+        self.record(format!(
+            "{} 创建并启动服务 {},地址:{}",
+            self.name(),
+            service_name(card_number),
+            address
+        ))
+    }
+
+    fn restart(&self, card_number: u32) -> Result<(), String> {
+        self.record(format!(
+            "{} 重启服务 {}",
+            self.name(),
+            service_name(card_number)
+        ))
+    }
+
+    fn stop(&self, card_number: u32) -> Result<(), String> {
+        self.record(format!(
+            "{} 停止并删除服务 {}",
+            self.name(),
+            service_name(card_number)
+        ))
+    }
+
+    fn status(&self, card_number: u32) -> Result<Status, String> {
+        self.inner.status(card_number)
+    }
+
+    fn owner(&self, pid: u32, pm2_id: Option<u32>) -> Option<u32> {
+        self.inner.owner(pid, pm2_id)
+    }
+
+    fn kill(&self, pid: u32) -> Result<(), String> {
+        self.record(format!("kill {}", pid))
+    }
+}
+
+pub fn service_name(card_number: u32) -> String {
+    format!("nimble{}", card_number)
+}
+
+fn card_number_of(name: &str) -> Option<u32> {
+    name.strip_prefix("nimble")?.parse::<u32>().ok()
+}
+
+/// 只查询状态的后端,不接管或清理已有进程,用于 `--dry-run`
+pub fn inspect(kind: SupervisorKind) -> Box<dyn Supervisor> {
+    match kind {
+        SupervisorKind::Native => Box::new(native::inspect()),
+        _ => new(kind),
+    }
+}
+
+pub fn new(kind: SupervisorKind) -> Box<dyn Supervisor> {
+    match kind {
+        SupervisorKind::Pm2 => Box::new(Pm2Supervisor::new()),
+        SupervisorKind::Systemd => Box::new(SystemdSupervisor::new()),
+        SupervisorKind::Native => Box::new(native::native()),
+    }
+}
+
+pub struct Pm2Supervisor {
+    script: PathBuf,
+}
+
+impl Default for Pm2Supervisor {
+    fn default() -> Self {
+        Pm2Supervisor::new()
+    }
+}
+
+impl Pm2Supervisor {
+    pub fn new() -> Pm2Supervisor {
+        Pm2Supervisor {
+            script: std::env::current_dir().unwrap().join("execute.sh"),
+        }
+    }
+
+    fn execute(&self, action: Action, card_number: u32, address: &str) -> Result<(), String> {
+        let script = self.script.to_string_lossy().to_string();
+        let card_number = card_number.to_string();
+        let args = match action {
+            Action::START => vec![script.as_str(), "start", &card_number, address],
+            Action::RESTART => {
+                info!("正在重启挖矿程序!");
+                vec![script.as_str(), "restart", &card_number]
+            }
+            Action::STOP => {
+                info!("正在停止挖矿程序!");
+                vec![script.as_str(), "stop", &card_number]
+            }
+            Action::SKIP => return Ok(()),
+        };
+        let output = Command::new("bash")
+            .args(&args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| e.to_string())?;
+        check_output(&args[..3].join(" "), &output)?;
+        Ok(())
+    }
+}
+
+impl Supervisor for Pm2Supervisor {
+    fn name(&self) -> &'static str {
+        "pm2"
+    }
+
+    fn start(&self, card_number: u32, address: &str) -> Result<(), String> {
+        check_address(address)?;
+        self.execute(Action::START, card_number, address)
+    }
+
+    fn restart(&self, card_number: u32) -> Result<(), String> {
+        self.execute(Action::RESTART, card_number, "")
+    }
+
+    fn stop(&self, card_number: u32) -> Result<(), String> {
+        self.execute(Action::STOP, card_number, "")
+    }
+
+    fn status(&self, card_number: u32) -> Result<Status, String> {
+        let process = pm::Process::new().map_err(|e| format!("pm2运行失败:{}", e))?;
+        Ok(match process.get_action(&service_name(card_number)) {
+            Action::SKIP => Status::Online,
+            Action::RESTART => Status::Stopped,
+            _ => Status::Missing,
+        })
+    }
+
+    fn owner(&self, _pid: u32, pm2_id: Option<u32>) -> Option<u32> {
+        let process = pm::Process::new().ok()?;
+        card_number_of(&process.get_name(pm2_id?)?)
+    }
+}
+
+pub struct SystemdSupervisor {
+    unit_dir: PathBuf,
+    workdir: PathBuf,
+    proc_root: PathBuf,
+}
+
+impl Default for SystemdSupervisor {
+    fn default() -> Self {
+        SystemdSupervisor::new()
+    }
+}
+
+impl SystemdSupervisor {
+    pub fn new() -> SystemdSupervisor {
+        let home = std::env::var("HOME").unwrap_or_default();
+        SystemdSupervisor::with_dirs(
+            PathBuf::from(home).join(".config/systemd/user"),
+            std::env::current_dir().unwrap(),
+            "/proc",
+        )
+    }
+
+    pub fn with_dirs(
+        unit_dir: impl Into<PathBuf>,
+        workdir: impl Into<PathBuf>,
+        proc_root: impl Into<PathBuf>,
+    ) -> SystemdSupervisor {
+        SystemdSupervisor {
+            unit_dir: unit_dir.into(),
+            workdir: workdir.into(),
+            proc_root: proc_root.into(),
+        }
+    }
+
+    /// 与 execute.sh start 等价的服务定义
+    pub fn unit(&self, card_number: u32, address: &str) -> String {
+        let log = self.workdir.join("logs").join(format!("{}.txt", address));
+        format!(
+            "[Unit]\nDescription=nimble miner on GPU {card_number}\n\n\
+             [Service]\nWorkingDirectory={workdir}\n\
+             Environment=CUDA_VISIBLE_DEVICES={card_number}\n\
+             ExecStart=/bin/bash -lc 'make run addr={address}'\n\
+             StandardOutput=append:{log}\nStandardError=append:{log}\n\
+             Restart=on-failure\nRestartSec=10\n\n\
+             [Install]\nWantedBy=default.target\n",
+            workdir = self.workdir.join("nimble-miner-public").display(),
+            log = log.display(),
+        )
+    }
+
+    fn unit_file(&self, card_number: u32) -> PathBuf {
+        self.unit_dir
+            .join(format!("{}.service", service_name(card_number)))
+    }
+
+    fn output(&self, args: &[&str]) -> Result<Output, String> {
+        let output = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| e.to_string())?;
+        info!(
+            "运行:systemctl --user {},{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+        Ok(output)
+    }
+
+    fn systemctl(&self, args: &[&str]) -> Result<String, String> {
+        let output = self.output(args)?;
+        check_output(&format!("systemctl --user {}", args.join(" ")), &output)?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Supervisor for SystemdSupervisor {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn start(&self, card_number: u32, address: &str) -> Result<(), String> {
+        check_address(address)?;
+        std::fs::create_dir_all(&self.unit_dir).map_err(|e| e.to_string())?;
+        std::fs::write(self.unit_file(card_number), self.unit(card_number, address))
+            .map_err(|e| e.to_string())?;
+        self.systemctl(&["daemon-reload"])?;
+        self.systemctl(&["start", &service_name(card_number)])?;
+        Ok(())
+    }
+
+    fn restart(&self, card_number: u32) -> Result<(), String> {
+        self.systemctl(&["restart", &service_name(card_number)])?;
+        Ok(())
+    }
+
+    fn stop(&self, card_number: u32) -> Result<(), String> {
+        let unit_file = self.unit_file(card_number);
+        if let Err(e) = self.systemctl(&["stop", &service_name(card_number)]) {
+            // 服务定义已不存在时停止失败不影响结果
+            if unit_file.exists() {
+                return Err(e);
+            }
+            warn!("{}", e);
+        }
+        if unit_file.exists() {
+            std::fs::remove_file(unit_file).map_err(|e| e.to_string())?;
+            self.systemctl(&["daemon-reload"])?;
+        }
+        Ok(())
+    }
+
+    fn status(&self, card_number: u32) -> Result<Status, String> {
+        if !self.unit_file(card_number).exists() {
+            return Ok(Status::Missing);
+        }
+        // 未运行时 is-active 退出码非零,以输出的状态为准
+        let output = self.output(&["is-active", &service_name(card_number)])?;
+        let active = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if active.is_empty() {
+            check_output("systemctl --user is-active", &output)?;
+        }
+        Ok(match active.as_str() {
+            "active" | "activating" => Status::Online,
+            _ => Status::Stopped,
+        })
+    }
+
+    /// 从 /proc/[pid]/cgroup 中找出所属的 nimble{n}.service
+    fn owner(&self, pid: u32, _pm2_id: Option<u32>) -> Option<u32> {
+        let cgroup =
+            std::fs::read_to_string(self.proc_root.join(pid.to_string()).join("cgroup")).ok()?;
+        cgroup
+            .split(['/', '\n'])
+            .find_map(|part| card_number_of(part.strip_suffix(".service")?))
+    }
+}
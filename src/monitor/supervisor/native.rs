@@ -0,0 +1,471 @@
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use super::{service_name, Status, Supervisor};
+use crate::monitor::procfs::ProcScanner;
+
+lazy_static! {
+    static ref NATIVE: NativeSupervisor = {
+        let supervisor = NativeSupervisor::new();
+        supervisor.adopt();
+        supervisor
+    };
+}
+
+/// 进程内的挖矿进程管理,程序重启后通过pid文件接管仍在运行的进程
+pub fn native() -> NativeSupervisor {
+    NATIVE.clone()
+}
+
+/// 只读取pid文件中仍在运行的进程,不接管、不清理,用于 `--dry-run`
+pub fn inspect() -> NativeSupervisor {
+    let supervisor = NativeSupervisor::new();
+    supervisor.inspect();
+    supervisor
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MinerState {
+    Running,
+    /// 进程退出,等待重启
+    Backoff,
+    /// 短时间内反复崩溃,暂停自动重启直到 `Backoff::pause` 结束
+    CrashLoop,
+}
+
+/// 重启退避策略
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// 首次重启等待时长,之后每次翻倍
+    pub base: Duration,
+    pub max: Duration,
+    /// 在 `window` 内崩溃 `limit` 次视为崩溃循环
+    pub window: Duration,
+    pub limit: usize,
+    /// 持续运行超过该时长后清空崩溃记录
+    pub stable: Duration,
+    /// 崩溃循环后暂停拉起的时长,期间状态为 `Status::CrashLoop`
+    pub pause: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            base: Duration::from_secs(5),
+            max: Duration::from_secs(300),
+            window: Duration::from_secs(600),
+            limit: 5,
+            stable: Duration::from_secs(600),
+            pause: Duration::from_secs(1800),
+        }
+    }
+}
+
+struct Miner {
+    address: String,
+    /// 进程组id,即启动的 make 进程id
+    pid: Option<u32>,
+    state: MinerState,
+    crashes: Vec<Instant>,
+    /// 崩溃循环暂停拉起的截止时间
+    paused_until: Option<Instant>,
+    stop: Arc<AtomicBool>,
+}
+
+#[derive(Clone)]
+pub struct NativeSupervisor {
+    workdir: PathBuf,
+    miner_dir: PathBuf,
+    program: String,
+    /// 启动参数,`{address}` 替换为挖矿地址
+    args: Vec<String>,
+    backoff: Backoff,
+    proc: ProcScanner,
+    miners: Arc<std::sync::Mutex<HashMap<u32, Miner>>>,
+}
+
+impl Default for NativeSupervisor {
+    fn default() -> Self {
+        NativeSupervisor::new()
+    }
+}
+
+impl NativeSupervisor {
+    /// 在 nimble-miner-public 目录下运行 `make run addr={address}`
+    pub fn new() -> NativeSupervisor {
+        let workdir = std::env::current_dir().unwrap();
+        let miner_dir = workdir.join("nimble-miner-public");
+        NativeSupervisor::with_command(
+            workdir,
+            miner_dir,
+            "make",
+            vec!["run".to_string(), "addr={address}".to_string()],
+            Backoff::default(),
+        )
+    }
+
+    pub fn with_command(
+        workdir: impl Into<PathBuf>,
+        miner_dir: impl Into<PathBuf>,
+        program: &str,
+        args: Vec<String>,
+        backoff: Backoff,
+    ) -> NativeSupervisor {
+        NativeSupervisor {
+            workdir: workdir.into(),
+            miner_dir: miner_dir.into(),
+            program: program.to_string(),
+            args,
+            backoff,
+            proc: ProcScanner::default(),
+            miners: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn pid_file(&self, card_number: u32) -> PathBuf {
+        self.workdir
+            .join("run")
+            .join(format!("{}.pid", service_name(card_number)))
+    }
+
+    /// 与 `Logs::iter_log_files` 读取的日志路径一致
+    pub fn log_file(&self, address: &str) -> PathBuf {
+        self.workdir.join("logs").join(format!("{}.txt", address))
+    }
+
+    pub fn state(&self, card_number: u32) -> Option<MinerState> {
+        let miners = self.miners.lock().unwrap();
+        miners.get(&card_number).map(|miner| miner.state)
+    }
+
+    pub fn pid(&self, card_number: u32) -> Option<u32> {
+        let miners = self.miners.lock().unwrap();
+        miners.get(&card_number).and_then(|miner| miner.pid)
+    }
+
+    /// 进程仍在运行且启动时间与记录一致,避免pid被复用后误判
+    fn is_alive(&self, pid: u32, start_ticks: u64) -> bool {
+        self.proc
+            .process(pid)
+            .is_some_and(|process| process.start_ticks == start_ticks)
+    }
+
+    fn kill(pid: u32) {
+        // 先结束整个进程组(make 及其拉起的 python),失败时只结束该进程
+        let group = std::process::Command::new("kill")
+            .args(["-TERM", "--", &format!("-{}", pid)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if !group.map(|status| status.success()).unwrap_or(false) {
+            let _ = std::process::Command::new("kill")
+                .args(["-TERM", &pid.to_string()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
+    fn spawn_child(
+        &self,
+        card_number: u32,
+        address: &str,
+    ) -> Result<tokio::process::Child, String> {
+        let log_file = self.log_file(address);
+        let pid_file = self.pid_file(card_number);
+        for dir in [log_file.parent(), pid_file.parent()].into_iter().flatten() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let stdout = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_file)
+            .map_err(|e| format!("{}: {}", log_file.display(), e))?;
+        let stderr = stdout.try_clone().map_err(|e| e.to_string())?;
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.replace("{address}", address))
+            .collect::<Vec<String>>();
+        let mut command = std::process::Command::new(&self.program);
+        command
+            .args(args)
+            .current_dir(&self.miner_dir)
+            .env("CUDA_VISIBLE_DEVICES", card_number.to_string())
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            // 独立进程组,程序退出时不影响挖矿进程,停止时可结束整个进程组
+            .process_group(0);
+        let child = tokio::process::Command::from(command)
+            .kill_on_drop(false)
+            .spawn()
+            .map_err(|e| format!("{}: {}", self.program, e))?;
+        if let Some(pid) = child.id() {
+            let start_ticks = self
+                .proc
+                .process(pid)
+                .map(|process| process.start_ticks)
+                .unwrap_or_default();
+            std::fs::write(&pid_file, format!("{} {} {}", pid, start_ticks, address))
+                .map_err(|e| format!("{}: {}", pid_file.display(), e))?;
+        }
+        Ok(child)
+    }
+
+    /// 运行并守护单张显卡的挖矿进程,`adopted` 为接管的已有进程及其启动时间
+    async fn supervise(self, card_number: u32, address: String, mut adopted: Option<(u32, u64)>) {
+        let stop = {
+            let miners = self.miners.lock().unwrap();
+            match miners.get(&card_number) {
+                Some(miner) => Arc::clone(&miner.stop),
+                None => return,
+            }
+        };
+        loop {
+            let started = Instant::now();
+            let exit = match adopted.take() {
+                Some((pid, start_ticks)) => {
+                    while !stop.load(Ordering::SeqCst) && self.is_alive(pid, start_ticks) {
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                    }
+                    format!("接管的进程{}已退出", pid)
+                }
+                None => match self.spawn_child(card_number, &address) {
+                    Ok(mut child) => {
+                        let pid = child.id();
+                        info!(
+                            "显卡{}已启动挖矿进程:{:?},地址:{}",
+                            card_number, pid, address
+                        );
+                        self.update(card_number, &stop, |miner| {
+                            miner.pid = pid;
+                            miner.state = MinerState::Running;
+                        });
+                        match child.wait().await {
+                            Ok(status) => format!("挖矿进程退出:{}", status),
+                            Err(e) => format!("等待挖矿进程失败:{}", e),
+                        }
+                    }
+                    Err(e) => format!("启动挖矿进程失败:{}", e),
+                },
+            };
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let now = Instant::now();
+            let mut delay = None;
+            self.update(card_number, &stop, |miner| {
+                if started.elapsed() >= self.backoff.stable {
+                    miner.crashes.clear();
+                }
+                miner
+                    .crashes
+                    .retain(|crash| now.duration_since(*crash) < self.backoff.window);
+                miner.crashes.push(now);
+                miner.pid = None;
+                if miner.crashes.len() >= self.backoff.limit {
+                    miner.state = MinerState::CrashLoop;
+                    miner.paused_until = Some(now + self.backoff.pause);
+                } else {
+                    let times = miner.crashes.len().saturating_sub(1).min(16) as u32;
+                    delay = Some(self.backoff.max.min(self.backoff.base * 2u32.pow(times)));
+                    miner.state = MinerState::Backoff;
+                }
+            });
+            let _ = std::fs::remove_file(self.pid_file(card_number));
+            match delay {
+                Some(delay) => {
+                    warn!("显卡{}{},{:?}后重启", card_number, exit, delay);
+                    tokio::time::sleep(delay).await;
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
+                None => {
+                    error!(
+                        "显卡{}{},{:?}内崩溃{}次,{:?}内不再自动重启",
+                        card_number,
+                        exit,
+                        self.backoff.window,
+                        self.backoff.limit,
+                        self.backoff.pause
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    /// 仅当记录仍属于当前守护任务时更新
+    fn update(&self, card_number: u32, stop: &Arc<AtomicBool>, f: impl FnOnce(&mut Miner)) {
+        let mut miners = self.miners.lock().unwrap();
+        if let Some(miner) = miners.get_mut(&card_number) {
+            if Arc::ptr_eq(&miner.stop, stop) {
+                f(miner);
+            }
+        }
+    }
+
+    fn insert(&self, card_number: u32, address: &str, pid: Option<u32>) {
+        let mut miners = self.miners.lock().unwrap();
+        miners.insert(
+            card_number,
+            Miner {
+                address: address.to_string(),
+                pid,
+                state: MinerState::Running,
+                crashes: Vec::new(),
+                paused_until: None,
+                stop: Arc::new(AtomicBool::new(false)),
+            },
+        );
+    }
+
+    /// 接管pid文件中仍在运行的进程,清理失效的pid文件。
+    /// pid文件内容为 `{pid} {启动时间} {地址}`,启动时间不一致说明pid已被其他进程复用
+    pub fn adopt(&self) {
+        self.recover(true);
+    }
+
+    /// 只记录pid文件中仍在运行的进程,不守护也不删除失效的pid文件
+    pub fn inspect(&self) {
+        self.recover(false);
+    }
+
+    fn recover(&self, supervise: bool) {
+        let dir = self.workdir.join("run");
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return;
+        };
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            let card_number = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.strip_prefix("nimble")?.parse::<u32>().ok());
+            let content = std::fs::read_to_string(&path).unwrap_or_default();
+            let fields = content.split_whitespace().collect::<Vec<&str>>();
+            let recorded = match fields[..] {
+                [pid, start_ticks, address] => pid
+                    .parse::<u32>()
+                    .ok()
+                    .zip(start_ticks.parse::<u64>().ok())
+                    .map(|(pid, start_ticks)| (pid, start_ticks, address)),
+                _ => None,
+            };
+            match (card_number, recorded) {
+                (Some(card_number), Some((pid, start_ticks, address)))
+                    if self.is_alive(pid, start_ticks) =>
+                {
+                    self.insert(card_number, address, Some(pid));
+                    if supervise {
+                        info!("接管显卡{}的挖矿进程:{},地址:{}", card_number, pid, address);
+                        tokio::spawn(self.clone().supervise(
+                            card_number,
+                            address.to_string(),
+                            Some((pid, start_ticks)),
+                        ));
+                    }
+                }
+                _ => {
+                    warn!("pid文件已失效:{},{}", path.display(), content.trim());
+                    if supervise {
+                        let _ = std::fs::remove_file(&path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Supervisor for NativeSupervisor {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn start(&self, card_number: u32, address: &str) -> Result<(), String> {
+        super::check_address(address)?;
+        if matches!(
+            self.state(card_number),
+            Some(MinerState::Running | MinerState::Backoff)
+        ) {
+            return Err(format!("显卡{}的挖矿进程已在运行", card_number));
+        }
+        self.insert(card_number, address, None);
+        tokio::spawn(
+            self.clone()
+                .supervise(card_number, address.to_string(), None),
+        );
+        Ok(())
+    }
+
+    fn restart(&self, card_number: u32) -> Result<(), String> {
+        let address = {
+            let miners = self.miners.lock().unwrap();
+            miners
+                .get(&card_number)
+                .map(|miner| miner.address.clone())
+                .ok_or(format!("显卡{}没有挖矿进程", card_number))?
+        };
+        self.stop(card_number)?;
+        self.start(card_number, &address)
+    }
+
+    fn stop(&self, card_number: u32) -> Result<(), String> {
+        let miner = self.miners.lock().unwrap().remove(&card_number);
+        if let Some(miner) = miner {
+            miner.stop.store(true, Ordering::SeqCst);
+            if let Some(pid) = miner.pid {
+                NativeSupervisor::kill(pid);
+            }
+        }
+        let _ = std::fs::remove_file(self.pid_file(card_number));
+        Ok(())
+    }
+
+    /// 崩溃循环暂停期间为 `CrashLoop`,暂停结束后为 `Stopped`,由调和重新拉起
+    fn status(&self, card_number: u32) -> Result<Status, String> {
+        let miners = self.miners.lock().unwrap();
+        Ok(match miners.get(&card_number) {
+            Some(miner) => match miner.state {
+                MinerState::Running | MinerState::Backoff => Status::Online,
+                MinerState::CrashLoop
+                    if miner
+                        .paused_until
+                        .is_some_and(|until| Instant::now() < until) =>
+                {
+                    Status::CrashLoop
+                }
+                MinerState::CrashLoop => Status::Stopped,
+            },
+            None => Status::Missing,
+        })
+    }
+
+    /// 挖矿进程(python)与启动的 make 进程同属一个进程组
+    fn owner(&self, pid: u32, _pm2_id: Option<u32>) -> Option<u32> {
+        let pgrp = self.proc.process(pid).map(|process| process.pgrp);
+        let miners = self.miners.lock().unwrap();
+        miners
+            .iter()
+            .find(|(_, miner)| miner.pid.is_some() && (miner.pid == Some(pid) || miner.pid == pgrp))
+            .map(|(card_number, _)| *card_number)
+    }
+}
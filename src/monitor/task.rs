@@ -0,0 +1,170 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::{sync::watch, time::Instant};
+use tracing::{error, info, warn};
+
+use super::{nvidia::GeForces, supervisor, Monitor, MONITOR};
+use crate::log::{Logs, Massage, MsgType, LOG_CHANNEL, TAILER_TIMEOUT};
+use crate::shutdown;
+
+/// 显卡检测间隔
+const GPU_INTERVAL: Duration = Duration::from_secs(60);
+/// 挖矿进程检查间隔
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(10);
+/// 任务重启等待时间上限,任务运行超过该时长后重新从1秒开始计算
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// 运行任务,任务退出或 panic 后等待一段时间重新启动,等待时间每次翻倍,
+/// 收到退出通知后不再重启
+pub async fn supervise<F, Fut>(name: &'static str, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        match tokio::spawn(task()).await {
+            Ok(()) if shutdown::is_shutdown() => {
+                info!("任务{}已停止", name);
+                return;
+            }
+            Ok(()) => warn!("任务{}已退出", name),
+            Err(e) if e.is_panic() => error!("任务{}异常退出:{}", name, e),
+            Err(e) => {
+                warn!("任务{}已取消:{}", name, e);
+                return;
+            }
+        }
+        if shutdown::is_shutdown() {
+            info!("任务{}已停止", name);
+            return;
+        }
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = Duration::from_secs(1);
+        }
+        warn!("{}秒后重新启动任务{}", backoff.as_secs(), name);
+        if shutdown::sleep(backoff).await {
+            return;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// 定时检测显卡,变化时通知进程检查任务
+pub async fn poll_gpus(sender: Arc<watch::Sender<GeForces>>) {
+    loop {
+        match Monitor::detect_gpus().await {
+            Ok(nvidias) => {
+                let changed = sender.send_if_modified(|current| {
+                    if *current == nvidias {
+                        return false;
+                    }
+                    *current = nvidias;
+                    true
+                });
+                if changed {
+                    info!("显卡信息已更新:{:?}", *sender.borrow());
+                }
+            }
+            // 识别失败时保留上次的结果,避免误判显卡已移除
+            Err(e) => error!("获取显卡信息失败:{}", e),
+        }
+        if shutdown::sleep(GPU_INTERVAL).await {
+            return;
+        }
+    }
+}
+
+/// 检查挖矿进程并按计划停止/拉起,外部命令在锁外执行,收到退出通知时完成本轮处理后退出
+pub async fn reconcile(mut nvidias: watch::Receiver<GeForces>) {
+    loop {
+        // 扫描 /proc 较慢,在持有锁之前进行
+        let process = Monitor::py_pros().await.unwrap_or_default();
+        let plan = {
+            let monitor = Arc::clone(&MONITOR);
+            let mut monitor_locked = monitor.lock().await;
+            if nvidias.has_changed().unwrap_or(false) {
+                (*monitor_locked).nvidias = nvidias.borrow_and_update().clone();
+            }
+            (*monitor_locked).prepare(&process).await
+        };
+        match plan {
+            Ok(plan) if plan.is_healthy() => info!("服务正常!!"),
+            Ok(plan) => {
+                let kind = Monitor::get_config().await.supervisor;
+                let result = tokio::task::spawn_blocking(move || {
+                    Monitor::apply(supervisor::new(kind).as_ref(), &plan)
+                })
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result);
+                if let Err(e) = result {
+                    error!("处理挖矿进程失败:{}", e);
+                }
+            }
+            Err(e) => error!("调用程序失败:{}", e),
+        }
+        tokio::select! {
+            _ = nvidias.changed() => {}
+            _ = tokio::time::sleep(RECONCILE_INTERVAL) => {}
+            _ = shutdown::wait() => return,
+        }
+    }
+}
+
+/// 处理日志分析发来的重启请求和阶段事件,退出时只处理剩余的阶段事件,不再重启进程
+pub async fn handle_messages() {
+    let mut receiver = LOG_CHANNEL.1.lock().await;
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.recv() => msg,
+            _ = shutdown::wait() => break,
+        };
+        match msg {
+            Some(msg) => handle_message(msg).await,
+            None => return,
+        }
+    }
+    // 日志读取任务退出前会发出最后的阶段事件
+    Logs::wait_tailers(TAILER_TIMEOUT).await;
+    while let Ok(msg) = receiver.try_recv() {
+        if let MsgType::EVENT(_) = msg.msg_type {
+            handle_message(msg).await;
+        }
+    }
+}
+
+async fn handle_message(msg: Massage) {
+    match &msg.msg_type {
+        MsgType::RESTART => {
+            warn!("需要重启:{:?}", msg);
+            let (gpu_id, summary) = {
+                let monitor = Arc::clone(&MONITOR);
+                let monitor_locked = monitor.lock().await;
+                (
+                    monitor_locked.gpu_of(&msg.address),
+                    monitor_locked.gpu_summary(&msg.address),
+                )
+            };
+            // 显卡降频时算力低不一定是挖矿进程的问题,随重启原因一并上报
+            let msg = match summary.filter(|summary| summary.is_throttled()) {
+                Some(summary) => {
+                    warn!("地址{}所在显卡降频:{}", msg.address, summary);
+                    Massage {
+                        body: format!("{}(显卡{})", msg.body, summary),
+                        ..msg
+                    }
+                }
+                None => msg,
+            };
+            Monitor::restart(gpu_id, &msg).await;
+        }
+        MsgType::EVENT(event) => {
+            let monitor = Arc::clone(&MONITOR);
+            let mut monitor_locked = monitor.lock().await;
+            (*monitor_locked).on_event(&msg.address, event).await;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use nvml_wrapper::{
+    enum_wrappers::device::{EccCounter, MemoryError, TemperatureSensor},
+    error::NvmlError,
+    Nvml,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use super::{Monitor, MONITOR};
+use crate::shutdown;
+
+const MB: u64 = 1024 * 1024;
+
+/// 影响算力的降频原因,空闲、应用时钟设置等不计入
+pub const THROTTLING: [&str; 5] = [
+    "SW_POWER_CAP",
+    "HW_SLOWDOWN",
+    "SW_THERMAL_SLOWDOWN",
+    "HW_THERMAL_SLOWDOWN",
+    "HW_POWER_BRAKE_SLOWDOWN",
+];
+
+/// 单张显卡的一次采样
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuSample {
+    pub timestamp: i64,
+    /// 温度(℃)
+    pub temperature: u32,
+    /// 功耗(W)
+    pub power: f32,
+    /// 功耗上限(W)
+    pub power_limit: f32,
+    /// SM 利用率(%)
+    pub sm_util: u32,
+    /// 显存读写利用率(%)
+    pub mem_util: u32,
+    /// 已用显存(MB)
+    pub memory_used: u64,
+    /// NVML 报告的降频原因,如 SW_THERMAL_SLOWDOWN
+    pub throttle: Vec<String>,
+    /// 未纠正的 ECC 错误数,显卡不支持 ECC 时为空
+    pub ecc_errors: Option<u64>,
+}
+
+impl GpuSample {
+    /// 影响算力的降频原因
+    pub fn throttling(&self) -> impl Iterator<Item = &String> {
+        self.throttle
+            .iter()
+            .filter(|reason| THROTTLING.contains(&reason.as_str()))
+    }
+}
+
+/// 一段时间内采样的汇总,随心跳上报
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuSummary {
+    pub samples: usize,
+    pub temperature_avg: f32,
+    pub temperature_max: u32,
+    pub power_avg: f32,
+    pub power_limit: f32,
+    pub sm_util_avg: f32,
+    pub mem_util_avg: f32,
+    pub memory_used_max: u64,
+    /// 各降频原因出现的采样次数
+    pub throttled: BTreeMap<String, usize>,
+    /// 最近一次的 ECC 错误数
+    pub ecc_errors: Option<u64>,
+}
+
+impl GpuSummary {
+    /// 超过一半的采样处于降频状态
+    pub fn is_throttled(&self) -> bool {
+        let throttled = self.throttled.values().max().copied().unwrap_or_default();
+        throttled * 2 > self.samples
+    }
+}
+
+impl std::fmt::Display for GpuSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "温度:{:.0}℃(最高{}℃),功耗:{:.0}/{:.0}W,利用率:{:.0}%,显存:{}MB",
+            self.temperature_avg,
+            self.temperature_max,
+            self.power_avg,
+            self.power_limit,
+            self.sm_util_avg,
+            self.memory_used_max
+        )?;
+        if !self.throttled.is_empty() {
+            write!(f, ",降频:{:?}/{}次采样", self.throttled, self.samples)?;
+        }
+        if let Some(ecc_errors) = self.ecc_errors.filter(|errors| *errors > 0) {
+            write!(f, ",ECC错误:{}", ecc_errors)?;
+        }
+        Ok(())
+    }
+}
+
+/// 各显卡最近的采样,key 为显卡 UUID,超出容量时丢弃最早的采样
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuSamples {
+    capacity: usize,
+    samples: BTreeMap<String, VecDeque<GpuSample>>,
+}
+
+impl GpuSamples {
+    pub fn new(capacity: usize) -> GpuSamples {
+        GpuSamples {
+            capacity,
+            samples: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for samples in self.samples.values_mut() {
+            while samples.len() > capacity {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn push(&mut self, uuid: &str, sample: GpuSample) {
+        let samples = self.samples.entry(uuid.to_string()).or_default();
+        samples.push_back(sample);
+        while samples.len() > self.capacity {
+            samples.pop_front();
+        }
+    }
+
+    /// 只保留仍在的显卡
+    pub fn retain(&mut self, uuids: &[String]) {
+        self.samples.retain(|uuid, _| uuids.contains(uuid));
+    }
+
+    pub fn latest(&self, uuid: &str) -> Option<&GpuSample> {
+        self.samples.get(uuid)?.back()
+    }
+
+    pub fn summary(&self, uuid: &str) -> Option<GpuSummary> {
+        let samples = self
+            .samples
+            .get(uuid)
+            .filter(|samples| !samples.is_empty())?;
+        let count = samples.len();
+        let avg =
+            |value: fn(&GpuSample) -> f32| samples.iter().map(value).sum::<f32>() / count as f32;
+        let mut throttled = BTreeMap::<String, usize>::new();
+        for reason in samples.iter().flat_map(|sample| sample.throttling()) {
+            *throttled.entry(reason.clone()).or_default() += 1;
+        }
+        let latest = samples.back()?;
+        Some(GpuSummary {
+            samples: count,
+            temperature_avg: avg(|sample| sample.temperature as f32),
+            temperature_max: samples.iter().map(|sample| sample.temperature).max()?,
+            power_avg: avg(|sample| sample.power),
+            power_limit: latest.power_limit,
+            sm_util_avg: avg(|sample| sample.sm_util as f32),
+            mem_util_avg: avg(|sample| sample.mem_util as f32),
+            memory_used_max: samples.iter().map(|sample| sample.memory_used).max()?,
+            throttled,
+            ecc_errors: latest.ecc_errors,
+        })
+    }
+
+    pub fn summaries(&self) -> BTreeMap<String, GpuSummary> {
+        self.samples
+            .keys()
+            .filter_map(|uuid| Some((uuid.clone(), self.summary(uuid)?)))
+            .collect()
+    }
+}
+
+/// 采样全部显卡,返回 UUID 及采样,读取失败的显卡跳过
+pub fn sample(nvml: &Nvml, now: i64) -> Vec<(String, GpuSample)> {
+    let count = match nvml.device_count() {
+        Ok(count) => count,
+        Err(e) => {
+            error!("获取显卡数量失败:{}", e);
+            return Vec::new();
+        }
+    };
+    (0..count)
+        .filter_map(|index| match device_sample(nvml, index, now) {
+            Ok(sample) => Some(sample),
+            Err(e) => {
+                warn!("显卡{}采样失败:{}", index, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn device_sample(nvml: &Nvml, index: u32, now: i64) -> Result<(String, GpuSample), NvmlError> {
+    let device = nvml.device_by_index(index)?;
+    let utilization = device.utilization_rates()?;
+    let throttle = device
+        .current_throttle_reasons()?
+        .iter_names()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let sample = GpuSample {
+        timestamp: now,
+        temperature: device.temperature(TemperatureSensor::Gpu)?,
+        power: device.power_usage()? as f32 / 1000f32,
+        power_limit: device.enforced_power_limit()? as f32 / 1000f32,
+        sm_util: utilization.gpu,
+        mem_util: utilization.memory,
+        memory_used: device.memory_info()?.used / MB,
+        throttle,
+        // 消费级显卡不支持 ECC
+        ecc_errors: device
+            .total_ecc_errors(MemoryError::Uncorrected, EccCounter::Volatile)
+            .ok(),
+    };
+    Ok((device.uuid()?, sample))
+}
+
+/// 定时采集显卡运行数据,NVML 不可用时不采集
+pub async fn watch() {
+    let nvml = match tokio::task::spawn_blocking(Nvml::init).await {
+        Ok(Ok(nvml)) => Arc::new(nvml),
+        Ok(Err(e)) => {
+            warn!("NVML不可用,不采集显卡运行数据:{}", e);
+            shutdown::wait().await;
+            return;
+        }
+        Err(e) => {
+            error!("初始化NVML失败:{}", e);
+            return;
+        }
+    };
+    loop {
+        let config = Monitor::get_config().await.telemetry;
+        let sampler = Arc::clone(&nvml);
+        let now = chrono::Local::now().timestamp();
+        match tokio::task::spawn_blocking(move || sample(&sampler, now)).await {
+            Ok(samples) => {
+                let monitor = Arc::clone(&MONITOR);
+                let mut monitor_locked = monitor.lock().await;
+                let uuids = samples
+                    .iter()
+                    .map(|(uuid, _)| uuid.clone())
+                    .collect::<Vec<String>>();
+                monitor_locked.telemetry.set_capacity(config.samples);
+                monitor_locked.telemetry.retain(&uuids);
+                for (uuid, sample) in samples {
+                    monitor_locked.telemetry.push(&uuid, sample);
+                }
+            }
+            Err(e) => error!("显卡采样失败:{}", e),
+        }
+        if shutdown::sleep(std::time::Duration::from_secs(config.interval)).await {
+            return;
+        }
+    }
+}
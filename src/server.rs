@@ -1,18 +1,98 @@
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
+use std::sync::Arc;
 
 use actix_web::web;
-use actix_web::{get, post};
+use actix_web::{get, post, HttpRequest, HttpResponse};
 use tracing::error;
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::config::CONFIG;
+use crate::monitor::heartbeat::{verify, SIGNATURE_HEADER};
+use address::WALLETS_STATE;
 
 pub mod address;
+pub mod agent;
 pub mod clore;
 pub mod ssh;
 
+/// 节点启动时获取分配给该服务器的地址
 #[get("/distribute_address/{card_number}/{server_id}")]
-pub async fn distribute_address() -> String {
-    unimplemented!()
+pub async fn distribute_address(pathinfo: web::Path<(u32, u32)>) -> HttpResponse {
+    let (card_number, server_id) = pathinfo.into_inner();
+    let wallets = Arc::clone(&WALLETS_STATE);
+    let wallets_locked = wallets.lock().await;
+    let address = wallets_locked.server_address(server_id);
+    drop(wallets_locked);
+    if address.is_empty() {
+        warn!("服务器{}未分配地址", server_id);
+        return HttpResponse::NotFound().body(format!("服务器{}未分配地址", server_id));
+    }
+    if address.len() as u32 > card_number {
+        warn!(
+            "服务器{}分配了{}个地址,多于显卡数量{}",
+            server_id,
+            address.len(),
+            card_number
+        );
+    }
+    info!("服务器{}获取地址:{}", server_id, address.join(","));
+    HttpResponse::Ok().json(address)
+}
+
+#[post("/heartbeat")]
+pub async fn heartbeat(request: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let secret = {
+        let config = Arc::clone(&CONFIG);
+        let config_locked = config.lock().await;
+        config_locked.monitor.heartbeat.secret.expose().clone()
+    };
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let now = chrono::Local::now().timestamp();
+    let heartbeat = match verify(&secret, &body, signature, now) {
+        Ok(heartbeat) => heartbeat,
+        Err(e) => {
+            warn!("心跳校验失败:{}", e);
+            return HttpResponse::Unauthorized().body(e);
+        }
+    };
+    let wallets = Arc::clone(&WALLETS_STATE);
+    let mut wallets_locked = wallets.lock().await;
+    let updated = (*wallets_locked)
+        .receive_heartbeat(heartbeat.server_id, &heartbeat.addresses)
+        .await;
+    drop(wallets_locked);
+    if let Some(host) = heartbeat
+        .host
+        .as_ref()
+        .filter(|host| !host.issues.is_empty())
+    {
+        warn!(
+            "服务器{}主机异常:{:?},{}",
+            heartbeat.server_id,
+            host.issues,
+            host.last_oom.clone().unwrap_or_default()
+        );
+    }
+    for (uuid, summary) in heartbeat.telemetry.iter() {
+        let ecc_errors = summary.ecc_errors.unwrap_or_default() > 0;
+        if summary.is_throttled() || ecc_errors {
+            warn!("服务器{}显卡{}异常:{}", heartbeat.server_id, uuid, summary);
+        }
+    }
+    info!(
+        "服务器{}心跳(版本{}):显卡{}张,更新地址{:?},状态{:?}",
+        heartbeat.server_id,
+        heartbeat.version,
+        heartbeat.gpus.len(),
+        updated,
+        heartbeat.miners
+    );
+    HttpResponse::Ok().json(updated)
 }
 
 #[post("/printlnlog/{server_id}/{filename}")]
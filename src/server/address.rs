@@ -5,9 +5,13 @@ use strum::Display;
 use tokio::sync::Mutex;
 use tracing::{error, info, warn};
 
-use crate::{config::CONFIG, server::clore::Clore};
+use crate::{
+    config::{self, Strategy, CONFIG},
+    server::clore::Clore,
+    shutdown,
+};
 
-use super::{clore::model::CardType, ssh};
+use super::{agent, clore::model::Card, ssh};
 
 lazy_static::lazy_static! {
     pub static ref WALLETS_STATE:Arc<Mutex<Address>> = {
@@ -30,6 +34,12 @@ pub struct Wallet {
     pub start_time: Option<DateTime<Local>>,
     pub report_last_time: Option<DateTime<Local>>,
     pub deploy: Deployed,
+    /// 租用服务器使用的clore账户
+    #[serde(default)]
+    pub account: String,
+    /// 已从配置中移除但仍有租用中的服务器,订单取消或释放后不再跟踪
+    #[serde(default)]
+    pub retired: bool,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Display)]
@@ -58,6 +68,8 @@ impl Wallet {
             start_time: None,
             report_last_time: None,
             deploy: Deployed::NOTASSIGNED,
+            account: String::new(),
+            retired: false,
         }
     }
 
@@ -71,12 +83,31 @@ impl Wallet {
     }
 }
 
-#[derive(PartialEq, Debug)]
-pub struct Address(pub HashMap<String, Wallet>);
+/// clore账户的余额及订单
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct AccountState {
+    pub name: String,
+    pub balance: Option<f64>,
+    pub orders: Vec<u32>,
+    pub card_number: u32,
+}
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Address {
+    wallets: HashMap<String, Wallet>,
+    pub accounts: HashMap<String, AccountState>,
+}
 
 impl Display for Address {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let _ = f.write_str("\n");
+        for (name, account) in self.accounts.iter() {
+            let row = format!(
+                "account:{},balance:{:?},card_number:{},orders:{:?}\n",
+                name, account.balance, account.card_number, account.orders
+            );
+            let _ = f.write_str(&row);
+        }
         for (address, wallet) in (*self).iter() {
             if wallet.addr_type == AddressType::MASTER {
                 let row: String = format!(
@@ -161,7 +192,7 @@ impl Display for Address {
 
 impl std::ops::DerefMut for Address {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.wallets
     }
 }
 
@@ -169,13 +200,7 @@ impl std::ops::Deref for Address {
     type Target = HashMap<String, Wallet>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl Default for Address {
-    fn default() -> Self {
-        Self(Default::default())
+        &self.wallets
     }
 }
 
@@ -273,20 +298,180 @@ impl Address {
                 );
             }
         }
+
+        // 配置中已移除的地址不再参与调度,仍有租用服务器的保留跟踪,直到订单取消或释放
+        let removed = (*self)
+            .keys()
+            .filter(|address| {
+                !other_wallets.mst_address.contains(address)
+                    && !other_wallets.sub_address.contains(address)
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        for address in removed.iter() {
+            let wallet = (*self).get_mut(address).unwrap();
+            if wallet.deploy == Deployed::NOTASSIGNED {
+                (*self).remove(address);
+                info!("地址已从配置中移除:{}", address);
+            } else if !wallet.retired {
+                wallet.retired = true;
+                warn!(
+                    "地址已从配置中移除,但仍绑定服务器,订单取消或释放前继续跟踪:{:?}",
+                    wallet
+                );
+            }
+        }
+
+        // 主/子地址类型发生变更
+        for (address, wallet) in (*self).iter_mut() {
+            if removed.contains(address) {
+                continue;
+            }
+            if wallet.retired {
+                info!("地址重新加入配置:{}", address);
+                wallet.retired = false;
+            }
+            let addr_type = if other_wallets.mst_address.contains(address) {
+                AddressType::MASTER
+            } else {
+                AddressType::SUB
+            };
+            if wallet.addr_type != addr_type {
+                info!(
+                    "地址类型变更:{},{}->{}",
+                    address, wallet.addr_type, addr_type
+                );
+                wallet.addr_type = addr_type;
+            }
+        }
+    }
+
+    /// 已移除地址租用的订单,同一服务器上仍有配置中的地址时不取消
+    pub fn retired_orders(&self) -> Vec<(String, u32)> {
+        let mut orders: Vec<(String, u32)> = Vec::new();
+        for (_, wallet) in (*self).iter() {
+            if !wallet.retired {
+                continue;
+            }
+            let (orderid, serverid) = match &wallet.deploy {
+                Deployed::DEPLOYING {
+                    orderid, serverid, ..
+                }
+                | Deployed::DEPLOYED {
+                    orderid, serverid, ..
+                } => (*orderid, *serverid),
+                Deployed::NOTASSIGNED => continue,
+            };
+            let shared = (*self).iter().any(|(_, other)| {
+                !other.retired
+                    && match &other.deploy {
+                        Deployed::DEPLOYING { serverid: id, .. }
+                        | Deployed::DEPLOYED { serverid: id, .. } => *id == serverid,
+                        Deployed::NOTASSIGNED => false,
+                    }
+            });
+            if orderid != 0 && !shared && !orders.contains(&(wallet.account.clone(), orderid)) {
+                orders.push((wallet.account.clone(), orderid));
+            }
+        }
+        orders.sort();
+        orders
+    }
+
+    /// 订单已不在账户订单列表中(已取消或到期释放),不再跟踪已移除的地址,返回被移除的地址
+    pub fn forget_released(&mut self, account: &str, orders: &[u32]) -> Vec<String> {
+        let released = (*self)
+            .iter()
+            .filter(|(_, wallet)| wallet.retired && wallet.account == account)
+            .filter(|(_, wallet)| match &wallet.deploy {
+                Deployed::DEPLOYING { orderid, .. } | Deployed::DEPLOYED { orderid, .. } => {
+                    *orderid != 0 && !orders.contains(orderid)
+                }
+                Deployed::NOTASSIGNED => true,
+            })
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<String>>();
+        for address in released.iter() {
+            (*self).remove(address);
+            info!("已移除地址的订单已释放,不再跟踪:{}", address);
+        }
+        released
+    }
+
+    /// 按配置将子地址划分到clore账户
+    pub fn assign_accounts(&mut self, clore: &config::Clore) {
+        let names = clore
+            .get_accounts()
+            .into_iter()
+            .map(|account| account.name)
+            .collect::<Vec<String>>();
+        self.accounts.retain(|name, _| names.contains(name));
+        for name in names.iter() {
+            self.accounts
+                .entry(name.clone())
+                .or_insert_with(|| AccountState {
+                    name: name.clone(),
+                    ..Default::default()
+                });
+        }
+        for (address, wallet) in self.wallets.iter_mut() {
+            // 已移除的地址不在任何账户的地址列表中,保留租用时的账户直到订单取消或释放
+            if wallet.addr_type != AddressType::SUB || wallet.retired {
+                continue;
+            }
+            let account = clore.account_of(address);
+            if wallet.account != account {
+                if wallet.deploy != Deployed::NOTASSIGNED && !wallet.account.is_empty() {
+                    warn!(
+                        "地址{}已由账户{}租用服务器,账户变更为{}",
+                        address, wallet.account, account
+                    );
+                }
+                wallet.account = account;
+            }
+        }
     }
 
     /// 获取没有分配的挖矿地址
     pub async fn get_unused_wallet(&mut self) -> Vec<Wallet> {
         let mut wallets: Vec<Wallet> = Vec::new();
-        let result = Clore::default().my_orders().await;
+        let accounts = Clore::get_config().await.get_accounts();
+        for account in accounts.into_iter() {
+            wallets.extend(self.get_account_unused_wallet(account).await);
+        }
+        wallets
+    }
+
+    /// 获取某个clore账户下没有分配的挖矿地址
+    async fn get_account_unused_wallet(&mut self, account: config::Account) -> Vec<Wallet> {
+        let mut wallets: Vec<Wallet> = Vec::new();
+        let clore = Clore::new(account.clone());
+        let result = clore.my_orders().await;
 
         if let Ok(my_orders) = result {
+            let state = self
+                .accounts
+                .entry(account.name.clone())
+                .or_insert_with(|| AccountState {
+                    name: account.name.clone(),
+                    ..Default::default()
+                });
+            state.orders = my_orders.iter().map(|order| order.order_id).collect();
+            state.card_number = my_orders.get_total_card_number();
+            if let Ok(balance) = clore.wallet().await {
+                state.balance = Some(balance);
+            }
+            let order_ids = state.orders.clone();
+            self.forget_released(&account.name, &order_ids);
             let orders = (*my_orders).clone();
             // 过滤掉已经知道的serverid和钱包地址，已经知道的订单对应的不去链接ssh获取挖矿进程
             // 如果有对应的serverid但是orderid 为零，则补充上相关信息
             // 提取当钱包所有已知的serverid
             let mut serverids = Vec::new();
             for (_, wallet) in (*self).iter_mut() {
+                if wallet.account != account.name {
+                    continue;
+                }
                 match wallet.deploy {
                     Deployed::NOTASSIGNED => {}
                     Deployed::DEPLOYING {
@@ -321,8 +506,11 @@ impl Address {
                 }
             }
 
-            // 尝试远程ssh执行获取python挖矿进程。如果链接有出问题，则取消本次更新
-            let (lists, error) = ssh::Ssh::try_run_command_remote(&filter_orders).await;
+            // 优先通过节点http接口获取挖矿进程,无法访问的再尝试远程ssh。如果链接有出问题，则取消本次更新
+            let (mut lists, filter_orders) = agent::Agent::try_query_orders(&filter_orders).await;
+            let (ssh_lists, error) =
+                ssh::Ssh::try_run_command_remote(&filter_orders, &account.ssh_passwd).await;
+            lists.extend(ssh_lists);
 
             // 对ssh获取成功的进程，将服务器信息挂在到子钱包地址上去
             for (wallet_adress, deployed) in lists {
@@ -331,7 +519,10 @@ impl Address {
 
             // 返回没有租用服务器的钱包地址
             for (_, wallet) in (*self).iter_mut() {
-                if wallet.addr_type == AddressType::SUB && wallet.deploy == Deployed::NOTASSIGNED {
+                if wallet.addr_type == AddressType::SUB
+                    && wallet.deploy == Deployed::NOTASSIGNED
+                    && wallet.account == account.name
+                {
                     wallets.push(wallet.clone());
                 }
             }
@@ -340,12 +531,12 @@ impl Address {
             // 已经知道租用的显卡数量等于了地址数量
             // 则将清空未使用的钱包地址
             if !error.is_empty() {
-                warn!("ssh remote err:{:?}", error);
+                warn!("账户:{},ssh remote err:{:?}", account.name, error);
                 wallets.clear();
             }
 
-            if my_orders.get_total_card_number() == self.get_total_sub_addr() {
-                warn!("当前已经满卡");
+            if my_orders.get_total_card_number() == self.get_total_sub_addr(&account.name) {
+                warn!("账户:{},当前已经满卡", account.name);
                 wallets.clear();
             }
         }
@@ -354,41 +545,71 @@ impl Address {
 
     async fn resent_server(&mut self, wallets: Vec<Wallet>) {
         if wallets.len() > 0 {
-            let clore = Clore::default();
-            let markets = clore.marketplace().await;
-            let wallets = wallets.as_slice().chunks(2);
-            for wallet in wallets {
-                info!("需要租用卡:{:?},len:{}", wallet, wallet.len());
-                let address = wallet
-                    .iter()
-                    .map(|item| item.address.clone())
-                    .collect::<Vec<String>>();
-                if let Ok(cards) = &markets {
-                    for card in cards.iter() {
-                        // info!("len:{}",card.card_number);
-                        if card.card_type == CardType::NVIDIA4090
-                            && (card.card_number as usize) == wallet.len()
-                        {
-                            info!(
-                                "显卡租用中,服务器显卡数量:{},显卡型号:{},serverid:{}",
-                                card.card_number, card.card_type, card.server_id
-                            );
-                            if let Ok(_) = clore.create_order_web_api(card, address.clone()).await {
-                                for wallet_adress in address.iter() {
-                                    let _ = self
-                                        .assgin_server(
-                                            wallet_adress,
-                                            Deployed::DEPLOYING {
-                                                orderid: 0,
-                                                serverid: card.server_id,
-                                                sshaddr: None,
-                                                sshport: None,
-                                            },
-                                        )
-                                        .await;
-                                }
-                                break;
+            let config = Clore::get_config().await;
+            let strategy = Strategy::get_config().await;
+            let markets = Clore::default().marketplace().await;
+            // 按账户分组,各账户分别下单
+            let mut groups = indexmap::IndexMap::<String, Vec<Wallet>>::new();
+            for wallet in wallets.into_iter() {
+                groups
+                    .entry(wallet.account.clone())
+                    .or_default()
+                    .push(wallet);
+            }
+            for (name, wallets) in groups.iter() {
+                let account = match config.get_account(name) {
+                    Some(account) => account,
+                    None => {
+                        warn!("账户不存在:{:?},跳过地址:{:?}", name, wallets);
+                        continue;
+                    }
+                };
+                let clore = Clore::new(account);
+                self.resent_account_server(&clore, &strategy, &markets, wallets)
+                    .await;
+            }
+        }
+    }
+
+    async fn resent_account_server(
+        &mut self,
+        clore: &Clore,
+        strategy: &Strategy,
+        markets: &Result<Vec<Card>, String>,
+        wallets: &[Wallet],
+    ) {
+        let wallets = wallets.chunks(strategy.chunk_size);
+        for wallet in wallets {
+            info!("需要租用卡:{:?},len:{}", wallet, wallet.len());
+            let address = wallet
+                .iter()
+                .map(|item| item.address.clone())
+                .collect::<Vec<String>>();
+            if let Ok(cards) = markets {
+                for card in cards.iter() {
+                    // info!("len:{}",card.card_number);
+                    if strategy.market.card_types.contains(&card.card_type)
+                        && (card.card_number as usize) == wallet.len()
+                    {
+                        info!(
+                            "显卡租用中,服务器显卡数量:{},显卡型号:{},serverid:{}",
+                            card.card_number, card.card_type, card.server_id
+                        );
+                        if let Ok(_) = clore.create_order_web_api(card, address.clone()).await {
+                            for wallet_adress in address.iter() {
+                                let _ = self
+                                    .assgin_server(
+                                        wallet_adress,
+                                        Deployed::DEPLOYING {
+                                            orderid: 0,
+                                            serverid: card.server_id,
+                                            sshaddr: None,
+                                            sshport: None,
+                                        },
+                                    )
+                                    .await;
                             }
+                            break;
                         }
                     }
                 }
@@ -427,6 +648,7 @@ impl Address {
             return false;
         }
         let wallet = (*self).get_mut(wallet_adress).unwrap();
+        wallet.report_last_time = Some(Local::now());
         if let Deployed::DEPLOYING {
             orderid,
             serverid,
@@ -434,8 +656,7 @@ impl Address {
             sshport,
         } = &wallet.deploy
         {
-            let local_time = Local::now();
-            wallet.report_last_time = Some(local_time);
+            info!("地址{}已上报,订单{}部署完成", wallet_adress, orderid);
             wallet.deploy = Deployed::DEPLOYED {
                 orderid: orderid.clone(),
                 serverid: serverid.clone(),
@@ -447,37 +668,92 @@ impl Address {
         true
     }
 
+    /// 分配给该服务器的地址
+    pub fn server_address(&self, server_id: u32) -> Vec<String> {
+        let mut address = (*self)
+            .iter()
+            .filter(|(_, wallet)| match &wallet.deploy {
+                Deployed::DEPLOYING { serverid, .. } | Deployed::DEPLOYED { serverid, .. } => {
+                    *serverid == server_id
+                }
+                Deployed::NOTASSIGNED => false,
+            })
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<String>>();
+        address.sort();
+        address
+    }
+
+    /// 处理节点心跳,更新该服务器上各地址的上报时间,返回已更新的地址
+    pub async fn receive_heartbeat(&mut self, server_id: u32, addresses: &[String]) -> Vec<String> {
+        let mut updated = Vec::new();
+        for address in addresses.iter() {
+            let deployed_on = match (*self).get(address).map(|wallet| &wallet.deploy) {
+                Some(Deployed::DEPLOYING { serverid, .. })
+                | Some(Deployed::DEPLOYED { serverid, .. }) => Some(*serverid),
+                _ => None,
+            };
+            if deployed_on != Some(server_id) {
+                warn!(
+                    "服务器{}上报的地址{}未分配给该服务器:{:?}",
+                    server_id, address, deployed_on
+                );
+                continue;
+            }
+            if self.update_log_collect_time(address).await {
+                updated.push(address.clone());
+            }
+        }
+        updated
+    }
+
     // 超时未上报时间，则取消该机器订单号，重置所有钱包信息
-    pub async fn filter_log_timeout(&mut self, clore: &Clore) {
-        let mut order_ids: Vec<u32> = Vec::new();
+    pub async fn filter_log_timeout(&mut self) {
+        let strategy = Strategy::get_config().await;
+        let mut order_ids: Vec<(String, u32)> = Vec::new();
         for (_, wallet) in (*self).iter_mut() {
             let nowtime = Local::now();
             match &wallet.deploy {
                 Deployed::NOTASSIGNED => {}
                 Deployed::DEPLOYING { orderid, .. } => {
-                    // 创建时间超过25分钟，还未有上报时间则，进行取消订单
+                    // 创建时间超过deploying_timeout，还未有上报时间则，进行取消订单
                     if let Some(start_time) = wallet.start_time {
-                        if nowtime.timestamp() - start_time.timestamp() > 25 * 60 {
+                        if nowtime.timestamp() - start_time.timestamp()
+                            > strategy.deploying_timeout * 60
+                        {
                             if orderid != &0 {
-                                order_ids.push(orderid.clone());
+                                order_ids.push((wallet.account.clone(), *orderid));
                             }
                         }
                     }
                 }
                 Deployed::DEPLOYED { orderid, .. } => {
-                    // 上报时间若是超过了十分钟，则也取消，订单号
+                    // 上报时间若是超过了report_timeout，则也取消，订单号
                     if let Some(report_last_time) = wallet.report_last_time {
-                        if nowtime.timestamp() - report_last_time.timestamp() > 10 * 60 {
+                        if nowtime.timestamp() - report_last_time.timestamp()
+                            > strategy.report_timeout * 60
+                        {
                             if orderid != &0 {
-                                order_ids.push(orderid.clone());
+                                order_ids.push((wallet.account.clone(), *orderid));
                             }
                         }
                     }
                 }
             }
         }
-        for order_id in order_ids.iter() {
-            let result = clore.cancel_order(order_id.clone()).await;
+        // 已从配置中移除的地址,直接取消其独占服务器的订单
+        for order in self.retired_orders() {
+            if !order_ids.contains(&order) {
+                order_ids.push(order);
+            }
+        }
+        let config = Clore::get_config().await;
+        for (name, order_id) in order_ids.iter() {
+            let clore = match config.get_account(name) {
+                Some(account) => Clore::new(account),
+                None => Clore::default(),
+            };
+            let result = clore.cancel_order(*order_id).await;
             if let Err(e) = result {
                 error!("订单:{:?}取消失败,错误码：{:?}", order_id, e);
             } else {
@@ -486,10 +762,10 @@ impl Address {
         }
     }
 
-    fn get_total_sub_addr(&self) -> u32 {
+    fn get_total_sub_addr(&self, account: &str) -> u32 {
         let mut total_sub_addr = 0;
         for (_, address) in self.iter() {
-            if address.addr_type != AddressType::MASTER {
+            if address.addr_type != AddressType::MASTER && address.account == account {
                 total_sub_addr += 1;
             }
         }
@@ -503,9 +779,19 @@ pub async fn pool() {
         let mut locked = wallets.lock().await;
         let other = Address::default().load_address_file().await;
         locked.check(&other).await;
+        locked.assign_accounts(&Clore::get_config().await);
         let wallets = locked.get_unused_wallet().await;
         info!("当前绑定信息:{}", *locked);
         locked.resent_server(wallets).await;
+        if Strategy::get_config().await.auto_cancel {
+            locked.filter_log_timeout().await;
+        } else {
+            let retired = locked.retired_orders();
+            if !retired.is_empty() {
+                warn!("已移除地址仍在租用的订单,需手动取消:{:?}", retired);
+            }
+        }
+        drop(locked);
 
         // let address = wallets
         //     .iter()
@@ -524,8 +810,12 @@ pub async fn pool() {
         //         info!("server_ids:{:?}", server_ids);
         //     }
         // }
-        // drop(locked);
-        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        let interval = Strategy::get_config().await.interval;
+        // 收到退出通知时本轮调度已完成,直接退出
+        if shutdown::sleep(std::time::Duration::from_secs(interval)).await {
+            info!("调度已停止");
+            return;
+        }
         // tokio::time::sleep(std::time::Duration::from_secs(60 * 5)).await;
     }
 }
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use reqwest::{Client, ClientBuilder};
+use tracing::{info, warn};
+
+use super::address::Deployed;
+use super::clore::model::my_orders::Order;
+use crate::monitor::api::AgentStatus;
+
+/// 通过订单映射的http端口访问节点接口
+pub struct Agent {
+    url: String,
+    client: Client,
+}
+
+impl Agent {
+    pub fn new(url: &str) -> Result<Agent, String> {
+        let client = ClientBuilder::new()
+            .timeout(std::time::Duration::from_secs(5))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Agent {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+
+    pub fn from_order(order: &Order) -> Option<Agent> {
+        Agent::new(&order.get_http_url()?).ok()
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response, String> {
+        let url = format!("{}{}", self.url, path);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("{}: {}", url, response.status()));
+        }
+        Ok(response)
+    }
+
+    pub async fn healthz(&self) -> Result<(), String> {
+        self.get("/healthz").await.map(|_| ())
+    }
+
+    pub async fn status(&self) -> Result<AgentStatus, String> {
+        self.get("/status")
+            .await?
+            .json::<AgentStatus>()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn logs(&self, address: &str, tail: usize) -> Result<String, String> {
+        self.get(&format!("/logs/{}?tail={}", address, tail))
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// 通过节点接口获取各订单正在挖矿的地址,返回无法访问节点接口的订单,交由ssh处理
+    pub async fn try_query_orders(orders: &[Order]) -> (HashMap<String, Deployed>, Vec<Order>) {
+        let mut address = HashMap::<String, Deployed>::new();
+        let mut remaining = Vec::new();
+        for order in orders.iter() {
+            let Some(agent) = Agent::from_order(order) else {
+                remaining.push(order.clone());
+                continue;
+            };
+            match agent.status().await {
+                Ok(status) => {
+                    info!(
+                        "server_id:{},节点接口返回挖矿进程:{}个",
+                        order.server_id,
+                        status.miners.len()
+                    );
+                    for miner in status.miners.iter() {
+                        address.insert(
+                            miner.address.clone(),
+                            Deployed::DEPLOYED {
+                                orderid: order.order_id,
+                                serverid: order.server_id,
+                                sshaddr: order.get_ssh_host(),
+                                sshport: order.get_map_ssh_port(),
+                            },
+                        );
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "server_id:{}节点接口访问失败,改用ssh:{}",
+                        order.server_id, e
+                    );
+                    remaining.push(order.clone());
+                }
+            }
+        }
+        (address, remaining)
+    }
+}
@@ -1,4 +1,3 @@
-use futures::executor::block_on;
 use model::resent::ResentWeb;
 #[allow(dead_code)]
 use reqwest::{
@@ -13,29 +12,53 @@ use std::{
     io::{Read, Write},
     sync::Arc,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use self::model::{resent::Resent, Card};
 use crate::{
-    config::{self, CONFIG},
+    config::{
+        self,
+        secret::{self, Secret},
+        CONFIG,
+    },
     server::clore::model::{market::Marketplace, my_orders::MyOrders, wallet::Wallets},
 };
 
 pub mod model;
-pub struct Clore {}
 
-impl Default for Clore {
-    fn default() -> Self {
-        Self {}
-    }
+/// clore接口客户端,`account` 为空时使用配置中的第一个账户
+#[derive(Default)]
+pub struct Clore {
+    account: Option<config::Account>,
 }
 
 impl Clore {
+    pub fn new(account: config::Account) -> Clore {
+        Clore {
+            account: Some(account),
+        }
+    }
+
+    /// 当前使用的账户
+    pub async fn get_account(&self) -> config::Account {
+        match &self.account {
+            Some(account) => account.clone(),
+            None => Clore::get_config()
+                .await
+                .get_accounts()
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+        }
+    }
+
     pub async fn marketplace(&self) -> Result<Vec<Card>, String> {
         info!("获取市场数据");
         let config::Clore { api_host, .. } = Clore::get_config().await;
+        let strategy = config::Strategy::get_config().await;
+        let account = self.get_account().await;
         let url = format!("{}{}", api_host, "v1/marketplace");
-        let text = Clore::get_client()
+        let text = Clore::get_client(&account.api_token)
             .map_err(|e| e.to_string())?
             .get(url)
             .send()
@@ -50,7 +73,7 @@ impl Clore {
 
         let markets = serde_json::from_str::<Marketplace>(&text)
             .map_err(|e| e.to_string())?
-            .filter()
+            .filter(&strategy)
             .iter()
             .map(|card| card.clone())
             .collect::<Vec<_>>();
@@ -60,8 +83,9 @@ impl Clore {
 
     pub async fn wallet(&self) -> Result<f64, String> {
         let config::Clore { api_host, .. } = Clore::get_config().await;
+        let account = self.get_account().await;
         let url = format!("{}{}", api_host, "v1/wallets");
-        let text = Clore::get_client()
+        let text = Clore::get_client(&account.api_token)
             .map_err(|e| e.to_string())?
             .get(url)
             .send()
@@ -77,27 +101,54 @@ impl Clore {
         Ok(balance)
     }
 
+    async fn heartbeat_secret() -> Secret<String> {
+        let mutex_conf = Arc::clone(&CONFIG);
+        let config_locked = mutex_conf.lock().await;
+        config_locked.monitor.heartbeat.secret.clone()
+    }
+
+    /// 下单时传给节点的环境变量,心跳密钥通过 `HEARTBEAT_SECRET_ENV` 覆盖节点配置,
+    /// 节点无需在仓库配置中保存密钥
+    pub fn order_env(
+        card: &Card,
+        address: &[String],
+        heartbeat_secret: &Secret<String>,
+    ) -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert("SERVER_ID".to_string(), card.server_id.to_string());
+        env.insert("CARD_NUMBER".to_string(), card.card_number.to_string());
+        env.insert("ADDRESS".to_string(), address.join("-"));
+        if heartbeat_secret.expose().is_empty() {
+            warn!("未配置 monitor.heartbeat.secret,租用的节点不会上报心跳");
+        } else {
+            env.insert(
+                config::HEARTBEAT_SECRET_ENV.to_string(),
+                heartbeat_secret.expose().clone(),
+            );
+        }
+        env
+    }
+
     pub async fn create_order(&self, card: &Card, address: Vec<String>) -> Result<(), String> {
-        let config::Clore {
-            api_host,
+        let config::Clore { api_host, .. } = Clore::get_config().await;
+        let config::Account {
+            api_token,
             ssh_passwd,
             command,
             ..
-        } = Clore::get_config().await;
+        } = self.get_account().await;
         let url = format!("{}{}", api_host, "v1/create_order");
         let command = command
+            .unwrap_or_default()
             .replace("{server_id}", card.server_id.to_string().as_str())
             .replace("{card_number}", card.card_number.to_string().as_str())
             .replace("{address}", address.join("-").as_str());
         let mut resent = Resent::new(card.server_id, ssh_passwd, command);
-        let env = &mut resent.env;
-        env.insert("SERVER_ID".to_string(), card.server_id.to_string());
-        env.insert("CARD_NUMBER".to_string(), card.card_number.to_string());
-        env.insert("ADDRESS".to_string(), address.join("-"));
-        info!("body:{}", serde_json::to_string(&resent).unwrap());
+        resent.env = Clore::order_env(card, &address, &Clore::heartbeat_secret().await);
+        info!("body:{}", secret::redact(&resent));
         let mut headers: HashMap<_, _> = HashMap::new();
         headers.insert("Content-type", HeaderValue::from_str("application/json"));
-        let text = Clore::get_client()
+        let text = Clore::get_client(&api_token)
             .map_err(|e| e.to_string())?
             .post(url)
             .json(&resent)
@@ -128,26 +179,30 @@ impl Clore {
         card: &Card,
         address: Vec<String>,
     ) -> Result<(), String> {
-        let config::Clore {
-            web_api_host,
+        let config::Clore { web_api_host, .. } = Clore::get_config().await;
+        let config::Account {
+            name,
+            api_token,
             web_token,
             ssh_passwd,
             command,
             ..
-        } = Clore::get_config().await;
+        } = self.get_account().await;
         let url = format!("{}{}", web_api_host, "webapi/create_order");
         let command = command
+            .unwrap_or_default()
             .replace("{server_id}", card.server_id.to_string().as_str())
             .replace("{card_number}", card.card_number.to_string().as_str())
             .replace("{address}", address.join("-").as_str());
         let mut resent = ResentWeb::new(card.server_id, ssh_passwd, web_token, command.clone());
-        let env = &mut resent.env;
-        env.insert("SERVER_ID".to_string(), card.server_id.to_string());
-        env.insert("CARD_NUMBER".to_string(), card.card_number.to_string());
-        env.insert("ADDRESS".to_string(), address.join("-"));
-        info!("resent:{:?}", resent);
+        resent.env.extend(Clore::order_env(
+            card,
+            &address,
+            &Clore::heartbeat_secret().await,
+        ));
+        info!("账户:{},resent:{}", name, secret::redact(&resent));
 
-        let client = Clore::get_client().map_err(|e| e.to_string())?;
+        let client = Clore::get_client(&api_token).map_err(|e| e.to_string())?;
         info!("command:{:?}", command.clone());
         let text = client
             .post(url)
@@ -170,8 +225,9 @@ impl Clore {
 
     pub async fn my_orders(&self) -> Result<MyOrders, String> {
         let config::Clore { api_host, .. } = Clore::get_config().await;
+        let account = self.get_account().await;
         let url = format!("{}{}", api_host, "v1/my_orders");
-        let text = Clore::get_client()
+        let text = Clore::get_client(&account.api_token)
             .map_err(|e| e.to_string())?
             .get(url)
             .send()
@@ -184,7 +240,7 @@ impl Clore {
         let result: Result<MyOrders, String> =
             serde_json::from_str::<MyOrders>(&text).map_err(|e| e.to_string());
         if let Ok(my_orders) = &result {
-            info!("获取到订单号:\n{}", my_orders);
+            info!("账户:{},获取到订单号:\n{}", account.name, my_orders);
         } else {
             error!("获取订单失败:{:?}", result);
         }
@@ -194,19 +250,13 @@ impl Clore {
 
     pub async fn cancel_order(&self, order_id: u32) -> Result<(), String> {
         let config::Clore { api_host, .. } = Clore::get_config().await;
+        let account = self.get_account().await;
         let url = format!("{}{}", api_host, "v1/cancel_order");
         let body = format!(r#"{{"id":"{}"}}"#, order_id);
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            "Content-type",
-            HeaderValue::from_str("application/json").unwrap(),
-        );
-        let text = ClientBuilder::new()
-            .timeout(std::time::Duration::from_secs(30))
-            .default_headers(headers)
-            .build()
+        let text = Clore::get_client(&account.api_token)
             .map_err(|e| e.to_string())?
             .post(url)
+            .header("Content-type", "application/json")
             .body(body)
             .send()
             .await
@@ -234,14 +284,12 @@ impl Clore {
     }
 
     pub async fn cancel_order_web_api(&self, order_id: u32) -> Result<(), String> {
-        let config::Clore {
-            web_api_host,
-            web_token,
-            ..
-        } = Clore::get_config().await;
+        let config::Clore { web_api_host, .. } = Clore::get_config().await;
+        let config::Account { web_token, .. } = self.get_account().await;
         let body = format!(
             r#"{{"id":{},"rating":2,"token":"{}"}}"#,
-            order_id, web_token
+            order_id,
+            web_token.expose()
         );
         let url = format!("{}webapi/marketplace/cancel_order", web_api_host);
         let mut headers = HeaderMap::new();
@@ -271,11 +319,9 @@ impl Clore {
         }
     }
 
-    fn get_client() -> Result<Client, reqwest::Error> {
-        let config::Clore { api_token, .. } = block_on(Clore::get_config());
-        let token = api_token.clone();
+    fn get_client(api_token: &Secret<String>) -> Result<Client, reqwest::Error> {
         let mut headers = HeaderMap::new();
-        headers.insert("auth", HeaderValue::from_str(&token).unwrap());
+        headers.insert("auth", HeaderValue::from_str(api_token.expose()).unwrap());
         ClientBuilder::new()
             .default_headers(headers)
             .timeout(std::time::Duration::from_secs(30))
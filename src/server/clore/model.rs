@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
@@ -24,25 +24,39 @@ pub enum CardType {
 }
 
 impl CardType {
-    pub fn get_max_price(&self, card_number: f64) -> f64 {
-        let price = match self {
-            CardType::NVIDIA4090 => 32f64,
-            CardType::NVIDIA4080S => 24f64,
-            CardType::NVIDIA4080 => 20f64,
-            CardType::NVIDIA4070S => 32f64,
-            CardType::NVIDIA4070 => 17f64,
-            CardType::NVIDIA4070TI => 17f64,
-            CardType::NVIDIA3090 => 19f64,
-            CardType::NVIDIA3090TI => 19f64,
-            CardType::NVIDIA3080TI => 15f64,
-            CardType::NVIDIA3080 => 15f64,
-            CardType::NVIDIA1080TI => 10f64,
-            CardType::NVIDIA1080 => 10f64,
-            CardType::UNKNOWN(_) => 0f64,
-        };
+    /// 按配置 `strategy.max_price` 计算最高出价,未配置的型号为0
+    pub fn get_max_price(&self, max_price: &BTreeMap<String, f64>, card_number: f64) -> f64 {
+        let price = max_price
+            .get(&self.to_string())
+            .copied()
+            .unwrap_or_default();
 
         price * card_number
     }
+
+    /// 按显卡名称识别型号,如 `NVIDIA GeForce RTX 4070 Ti SUPER`,无法识别的型号为 UNKNOWN
+    pub fn from_name(name: &str) -> CardType {
+        let words = name.split_whitespace().collect::<Vec<&str>>();
+        let model = words
+            .iter()
+            .position(|word| word.chars().all(|c| c.is_ascii_digit()));
+        let (Some(factory), Some(index)) = (words.first(), model) else {
+            return CardType::UNKNOWN(name.to_string());
+        };
+        let flags = words[index + 1..]
+            .iter()
+            .map(|word| word.to_uppercase())
+            .collect::<Vec<String>>();
+        let flag = if flags.iter().any(|flag| flag == "SUPER") {
+            "S"
+        } else if flags.iter().any(|flag| flag == "TI") {
+            "TI"
+        } else {
+            ""
+        };
+        let card_type = format!("{}{}{}", factory.to_uppercase(), words[index], flag);
+        CardType::from_str(&card_type).unwrap_or_else(|_| CardType::UNKNOWN(name.to_string()))
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -92,14 +106,13 @@ pub mod market {
     use std::{
         collections::HashMap,
         ops::{Deref, DerefMut},
-        str::FromStr,
     };
 
     use regex::Regex;
     use serde::{Deserialize, Serialize};
-    use tracing::warn;
+    use tracing::{error, warn};
 
-    use crate::server::clore::Clore;
+    use crate::{config::Strategy, server::clore::Clore};
 
     use super::{Card, CardType};
 
@@ -138,31 +151,12 @@ pub mod market {
         }
 
         pub fn get_card_type(&self) -> CardType {
-            let card_info = self
-                .gpu
-                .split(" ")
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>();
-            let factory = card_info
-                .get(1)
-                .map(|item| item.to_owned())
-                .unwrap_or_default();
-            let card_type = card_info
-                .get(4)
-                .map(|item| item.to_owned())
-                .unwrap_or_default();
-            let mut flag = card_info
-                .get(5)
-                .map(|itme| itme.to_uppercase().to_owned())
-                .unwrap_or_default();
-            flag = match flag.as_str() {
-                "TI" => "TI".to_string(),
-                "SUPER" => "S".to_string(),
-                _ => "".to_string(),
+            // 格式如:2x NVIDIA GeForce RTX 3080
+            let name = match self.gpu.split_once(' ') {
+                Some((count, name)) if count.ends_with('x') => name,
+                _ => self.gpu.as_str(),
             };
-            let card_type = CardType::from_str(&format!("{}{}{}", factory, card_type, flag))
-                .unwrap_or_else(|_| CardType::UNKNOWN(card_info.join(" ")));
-            card_type
+            CardType::from_name(name)
         }
     }
 
@@ -192,9 +186,16 @@ pub mod market {
     }
 
     impl Marketplace {
-        pub fn filter(&self) -> Vec<Card> {
-            let regex_cpu = Regex::new(r"((?i)ryzen|intel)").unwrap();
-            let regex_gpu = Regex::new(r"(3080|3090|4070|4080|4080|4090)").unwrap();
+        pub fn filter(&self, strategy: &Strategy) -> Vec<Card> {
+            let market = &strategy.market;
+            let (regex_cpu, regex_gpu) =
+                match (Regex::new(&market.cpu_regex), Regex::new(&market.gpu_regex)) {
+                    (Ok(regex_cpu), Ok(regex_gpu)) => (regex_cpu, regex_gpu),
+                    (regex_cpu, regex_gpu) => {
+                        error!("筛选正则错误:{:?},{:?}", regex_cpu.err(), regex_gpu.err());
+                        return Vec::new();
+                    }
+                };
             let blocked_server_ids = Clore::import_block_server_ids();
             let mut cards: Vec<Card> = (*self)
                 .iter()
@@ -214,13 +215,13 @@ pub mod market {
                     #[allow(unused)]
                     let total = cpus.get(1).unwrap_or(&0u32);
                     regex_gpu.is_match(&gpu)
-                        && item.rating.get("avg").unwrap_or(&0f32) > &3f32
+                        && item.rating.get("avg").unwrap_or(&0f32) > &market.min_rating
                         && item.allowed_coins.contains(&"CLORE-Blockchain".to_string())
                         && !item.rented
-                        && item.mrl > 72
-                        && item.specs.net.down > 25f64
+                        && item.mrl > market.min_mrl
+                        && item.specs.net.down > market.min_net_down
                         && regex_cpu.is_match(cpu)
-                        && used >= &8u32
+                        && used >= &market.min_cpus
                 })
                 .filter(|card| !blocked_server_ids.contains(&card.id))
                 .map(|item| {
@@ -257,14 +258,14 @@ pub mod market {
                     card
                 })
                 .filter(|item| {
-                    let total_max_price = item.card_type.get_max_price(1f64);
+                    let total_max_price = item.card_type.get_max_price(&strategy.max_price, 1f64);
                     match item.card_type {
                         CardType::UNKNOWN(_) => {
                             warn!("未知显卡:{:?}", item.card_type);
                             false
                         }
                         _ if total_max_price > item.avg_price_demand
-                            && item.card_type == CardType::NVIDIA4090 =>
+                            && market.card_types.contains(&item.card_type) =>
                         {
                             true
                         }
@@ -284,8 +285,9 @@ pub mod market {
                     item.card_number,
                     item.price_demand,
                     item.avg_price_demand,
-                    item.card_type.get_max_price(item.card_number.clone() as f64),
-                    item.card_type.get_max_price(1f64)
+                    item.card_type
+                        .get_max_price(&strategy.max_price, item.card_number as f64),
+                    item.card_type.get_max_price(&strategy.max_price, 1f64)
                 );
                 println!("{:?}", log);
             }
@@ -355,6 +357,7 @@ pub mod resent {
     use std::collections::HashMap;
 
     use super::Currency;
+    use crate::config::secret::Secret;
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Resent {
@@ -365,12 +368,12 @@ pub mod resent {
         pub demand: String,
         pub ports: HashMap<String, String>,
         pub env: HashMap<String, String>,
-        pub ssh_password: String,
+        pub ssh_password: Secret<String>,
         pub command: String,
     }
 
     impl Resent {
-        pub fn new(server_id: u32, ssh_passwd: String, command: String) -> Resent {
+        pub fn new(server_id: u32, ssh_passwd: Secret<String>, command: String) -> Resent {
             let mut ports = HashMap::<String, String>::new();
 
             ports.insert("22".to_string(), "tcp".to_string());
@@ -405,21 +408,24 @@ pub mod resent {
         pub demand: String,
         pub renting_server: u32,
         pub remember_password: bool,
-        pub token: String,
+        pub token: Secret<String>,
+        /// 与 api 下单一致通过 ssh_password 字段设置密码,
+        /// 网页下单接口是否支持该字段尚未确认,因此仍保留环境变量
+        pub ssh_password: Secret<String>,
         pub command: String,
     }
 
     impl ResentWeb {
         pub fn new(
             server_id: u32,
-            ssh_passwd: String,
-            web_token: String,
+            ssh_passwd: Secret<String>,
+            web_token: Secret<String>,
             command: String,
         ) -> ResentWeb {
             let mut envs = HashMap::<String, String>::new();
-            // {"WEBUI_PASSWORD":"MTcxNjMwNDc2N19ZempBSW","SSH_PASSWORD":"MTcxNjMwNDc2N19ZempBSW"}
-            envs.insert("WEBUI_PASSWORD".to_string(), ssh_passwd.clone());
-            envs.insert("SSH_PASSWORD".to_string(), ssh_passwd.clone());
+            // 镜像通过这两个环境变量设置ssh及webui密码,打印时需使用 secret::redact
+            envs.insert("WEBUI_PASSWORD".to_string(), ssh_passwd.expose().clone());
+            envs.insert("SSH_PASSWORD".to_string(), ssh_passwd.expose().clone());
             let mut ports = HashMap::<String, String>::new();
 
             ports.insert("22".to_string(), "tcp".to_string());
@@ -433,7 +439,8 @@ pub mod resent {
                 demand: "on-demand".to_string(),
                 renting_server: server_id,
                 remember_password: true,
-                token: web_token.to_string(),
+                token: web_token,
+                ssh_password: ssh_passwd,
                 command,
             }
         }
@@ -528,6 +535,21 @@ pub mod my_orders {
             ssh_map_port
         }
 
+        /// 节点http服务地址,http_port 可能是完整url、host:port 或端口号
+        pub fn get_http_url(&self) -> Option<String> {
+            let http_port = self.http_port.trim().trim_end_matches('/');
+            if http_port.is_empty() {
+                None
+            } else if http_port.contains("://") {
+                Some(http_port.to_string())
+            } else if http_port.contains(':') {
+                Some(format!("http://{}", http_port))
+            } else {
+                let port = http_port.parse::<u16>().ok()?;
+                Some(format!("http://{}:{}", self.get_ssh_host()?, port))
+            }
+        }
+
         pub fn get_ssh_host(&self) -> Option<String> {
             let index = self.pub_cluster.len();
             if index > 0 {
@@ -9,8 +9,8 @@ use ssh2::Session;
 use tracing::info;
 use tracing::warn;
 
+use crate::config::secret::Secret;
 use crate::server::address::Deployed;
-use crate::server::clore::Clore;
 
 use super::clore::model::my_orders::Order;
 
@@ -19,8 +19,8 @@ pub struct Ssh {}
 impl Ssh {
     pub async fn try_run_command_remote(
         orders: &Vec<Order>,
+        ssh_passwd: &Secret<String>,
     ) -> (HashMap<String, Deployed>, Vec<u32>) {
-        let config = Clore::get_config().await;
         let mut address = HashMap::<String, Deployed>::new();
         let mut errors = Vec::new();
         for order in orders.iter() {
@@ -42,7 +42,7 @@ impl Ssh {
             let result = Ssh::get_remote_ip(sshaddr.clone(), sshport).await;
             if result.is_ok() {
                 let result = Ssh::exec_to_remote(
-                    config.ssh_passwd.clone(),
+                    ssh_passwd.clone(),
                     result.unwrap(),
                     "ps -aeo command |grep execute.py |grep -v grep",
                 );
@@ -120,7 +120,7 @@ impl Ssh {
     }
 
     pub fn exec_to_remote(
-        ssh_passwd: String,
+        ssh_passwd: Secret<String>,
         socket_addr: SocketAddr,
         ssh_command: &str,
     ) -> Result<Vec<String>, String> {
@@ -131,7 +131,7 @@ impl Ssh {
         let mut sess = Session::new().map_err(|e| e.to_string())?;
         sess.set_tcp_stream(tcp);
         sess.handshake().map_err(|e| e.to_string())?;
-        sess.userauth_password("root", &ssh_passwd)
+        sess.userauth_password("root", ssh_passwd.expose())
             .map_err(|e| e.to_string())?;
 
         let mut channel = sess.channel_session().map_err(|e| e.to_string())?;
@@ -142,6 +142,7 @@ impl Ssh {
             .read_to_string(&mut output)
             .map_err(|e| e.to_string());
         let _ = channel.wait_close();
+        let _ = sess.disconnect(None, "done", None);
         if result.is_ok() {
             info!("ssh运行结果:\n{}", output);
             let reg: regex::Regex = regex::Regex::new(r"(nimble[\w]+)").unwrap();
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{error, warn};
+
+lazy_static! {
+    /// 退出通知,收到 SIGTERM/SIGINT 后置为 true
+    static ref SHUTDOWN: watch::Sender<bool> = watch::channel(false).0;
+}
+
+/// 等待 SIGTERM/SIGINT 并通知各任务完成当前处理后退出,再次收到信号时立即退出
+pub async fn listen() {
+    let (mut terminate, mut interrupt) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("注册退出信号失败:{}", e);
+            return;
+        }
+    };
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    };
+    warn!("收到{},完成当前处理后退出,再次发送将立即退出", name);
+    trigger();
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = interrupt.recv() => {}
+    }
+    warn!("再次收到退出信号,立即退出");
+    std::process::exit(130);
+}
+
+/// 通知各任务退出
+pub fn trigger() {
+    SHUTDOWN.send_replace(true);
+}
+
+/// 是否已收到退出通知
+pub fn is_shutdown() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// 等待退出通知
+pub async fn wait() {
+    let mut receiver = SHUTDOWN.subscribe();
+    let _ = receiver.wait_for(|shutdown| *shutdown).await;
+}
+
+/// 等待一段时间,期间收到退出通知时提前返回 true
+pub async fn sleep(duration: Duration) -> bool {
+    tokio::select! {
+        _ = wait() => true,
+        _ = tokio::time::sleep(duration) => false,
+    }
+}
@@ -3,7 +3,7 @@ pub mod common;
 mod wallet {
     use std::any::Any;
 
-    use monitor::server::address::{Address, Wallet};
+    use monitor::server::address::{Address, AddressType, Deployed, Wallet};
     use tracing::info;
 
     #[tokio::test]
@@ -14,4 +14,222 @@ mod wallet {
         info!("{:?}", address);
         assert_eq!(std::any::TypeId::of::<Vec<Wallet>>(), address.type_id())
     }
+
+    #[tokio::test]
+    async fn check_removed_address_test() {
+        crate::common::setup();
+        let mut instance = Address::default();
+        let mut config = monitor::config::Address {
+            mst_address: Vec::new(),
+            sub_address: vec!["nimble1a".to_string(), "nimble1b".to_string()],
+        };
+        instance.check(&config).await;
+        assert_eq!(2, instance.len());
+        let _ = instance
+            .assgin_server(
+                "nimble1a",
+                Deployed::DEPLOYING {
+                    orderid: 1,
+                    serverid: 1,
+                    sshaddr: None,
+                    sshport: None,
+                },
+            )
+            .await;
+
+        config.sub_address = vec!["nimble1a".to_string(), "nimble1c".to_string()];
+        instance.check(&config).await;
+        assert_eq!(2, instance.len());
+        assert!(!instance.contains_key("nimble1b"));
+        assert_eq!(
+            AddressType::SUB,
+            instance.get("nimble1c").unwrap().addr_type
+        );
+        assert_ne!(
+            Deployed::NOTASSIGNED,
+            instance.get("nimble1a").unwrap().deploy
+        );
+    }
+
+    #[tokio::test]
+    async fn retired_deployed_address_test() {
+        crate::common::setup();
+        let mut instance = Address::default();
+        let mut config = monitor::config::Address {
+            mst_address: Vec::new(),
+            sub_address: vec![
+                "nimble1a".to_string(),
+                "nimble1b".to_string(),
+                "nimble1c".to_string(),
+            ],
+        };
+        instance.check(&config).await;
+        for (address, orderid, serverid) in
+            [("nimble1a", 1, 1), ("nimble1b", 2, 2), ("nimble1c", 2, 2)]
+        {
+            let _ = instance
+                .assgin_server(
+                    address,
+                    Deployed::DEPLOYED {
+                        orderid,
+                        serverid,
+                        sshaddr: None,
+                        sshport: None,
+                    },
+                )
+                .await;
+        }
+
+        // 已租用服务器的地址从配置中移除后继续跟踪
+        config.sub_address = vec!["nimble1c".to_string()];
+        instance.check(&config).await;
+        assert_eq!(3, instance.len());
+        assert!(instance.get("nimble1a").unwrap().retired);
+        assert!(instance.get("nimble1b").unwrap().retired);
+        assert!(!instance.get("nimble1c").unwrap().retired);
+        // 服务器2上仍有配置中的地址,只取消服务器1的订单
+        assert_eq!(vec![(String::new(), 1)], instance.retired_orders());
+
+        // 订单1仍在账户订单中,不移除
+        assert!(instance.forget_released("", &[1, 2]).is_empty());
+        assert_eq!(
+            vec!["nimble1a".to_string()],
+            instance.forget_released("", &[2])
+        );
+        assert!(!instance.contains_key("nimble1a"));
+
+        // 重新加入配置后恢复调度
+        config.sub_address = vec!["nimble1b".to_string(), "nimble1c".to_string()];
+        instance.check(&config).await;
+        assert!(!instance.get("nimble1b").unwrap().retired);
+        assert!(instance.retired_orders().is_empty());
+
+        // 多账户:移除的地址保留租用时的账户,不被划分到第一个账户
+        let mut clore = monitor::config::Config::import_config().unwrap().clore;
+        clore.accounts = vec![
+            monitor::config::Account {
+                name: "a".to_string(),
+                ..Default::default()
+            },
+            monitor::config::Account {
+                name: "b".to_string(),
+                addresses: vec!["nimble1d".to_string()],
+                ..Default::default()
+            },
+        ];
+        config.sub_address.push("nimble1d".to_string());
+        instance.check(&config).await;
+        instance.assign_accounts(&clore);
+        assert_eq!("b", instance.get("nimble1d").unwrap().account);
+        let _ = instance
+            .assgin_server(
+                "nimble1d",
+                Deployed::DEPLOYED {
+                    orderid: 3,
+                    serverid: 3,
+                    sshaddr: None,
+                    sshport: None,
+                },
+            )
+            .await;
+        config.sub_address.pop();
+        clore.accounts[1].addresses.clear();
+        instance.check(&config).await;
+        instance.assign_accounts(&clore);
+        let wallet = instance.get("nimble1d").unwrap();
+        assert!(wallet.retired);
+        assert_eq!("b", wallet.account);
+        assert_eq!(vec![("b".to_string(), 3)], instance.retired_orders());
+        // 其他账户的订单列表中没有该订单,不影响跟踪
+        assert!(instance.forget_released("a", &[]).is_empty());
+        assert!(instance.forget_released("b", &[3]).is_empty());
+        assert_eq!(
+            vec!["nimble1d".to_string()],
+            instance.forget_released("b", &[])
+        );
+    }
+
+    #[tokio::test]
+    async fn receive_heartbeat_test() {
+        crate::common::setup();
+        let mut instance = Address::default();
+        let config = monitor::config::Address {
+            mst_address: Vec::new(),
+            sub_address: vec!["nimble1a".to_string(), "nimble1b".to_string()],
+        };
+        instance.check(&config).await;
+        let _ = instance
+            .assgin_server(
+                "nimble1a",
+                Deployed::DEPLOYING {
+                    orderid: 1,
+                    serverid: 7,
+                    sshaddr: None,
+                    sshport: None,
+                },
+            )
+            .await;
+
+        let addresses = vec![
+            "nimble1a".to_string(),
+            "nimble1b".to_string(),
+            "nimble1c".to_string(),
+        ];
+        // 其他服务器上报的地址不更新
+        assert!(instance.receive_heartbeat(8, &addresses).await.is_empty());
+        assert_eq!(
+            vec!["nimble1a".to_string()],
+            instance.receive_heartbeat(7, &addresses).await
+        );
+        let wallet = instance.get("nimble1a").unwrap();
+        assert!(wallet.report_last_time.is_some());
+        assert_eq!(
+            Deployed::DEPLOYED {
+                orderid: 1,
+                serverid: 7,
+                sshaddr: None,
+                sshport: None,
+            },
+            wallet.deploy
+        );
+        assert!(instance.get("nimble1b").unwrap().report_last_time.is_none());
+        assert_eq!(vec!["nimble1a".to_string()], instance.server_address(7));
+        assert!(instance.server_address(8).is_empty());
+
+        let last = instance.get("nimble1a").unwrap().report_last_time;
+        instance.receive_heartbeat(7, &addresses).await;
+        assert!(instance.get("nimble1a").unwrap().report_last_time > last);
+    }
+
+    #[tokio::test]
+    async fn assign_accounts_test() {
+        crate::common::setup();
+        let mut instance = Address::default();
+        let config = monitor::config::Address {
+            mst_address: Vec::new(),
+            sub_address: vec!["nimble1a".to_string(), "nimble1b".to_string()],
+        };
+        instance.check(&config).await;
+        let mut clore = monitor::config::Config::import_config().unwrap().clore;
+        clore.accounts = vec![
+            monitor::config::Account {
+                name: "a".to_string(),
+                ..Default::default()
+            },
+            monitor::config::Account {
+                name: "b".to_string(),
+                addresses: vec!["nimble1b".to_string()],
+                ..Default::default()
+            },
+        ];
+        instance.assign_accounts(&clore);
+        assert_eq!("a", instance.get("nimble1a").unwrap().account);
+        assert_eq!("b", instance.get("nimble1b").unwrap().account);
+        assert_eq!(2, instance.accounts.len());
+
+        clore.accounts.remove(0);
+        instance.assign_accounts(&clore);
+        assert_eq!("b", instance.get("nimble1a").unwrap().account);
+        assert!(!instance.accounts.contains_key("a"));
+    }
 }
@@ -0,0 +1,73 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use actix_web::{test, App};
+    use chrono::Local;
+    use monitor::config::Restart;
+    use monitor::monitor::api::{self, tail_lines};
+    use monitor::monitor::policy::RestartPolicy;
+    use monitor::server::agent::Agent;
+
+    use crate::common;
+
+    #[actix_web::test]
+    async fn tail_lines_test() {
+        common::setup();
+        let path = std::env::temp_dir().join("monitor_tail.txt");
+        let content = (0..5000)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<String>>();
+        std::fs::write(&path, content.join("\n") + "\n").unwrap();
+        assert_eq!(
+            vec!["line 4998", "line 4999"],
+            tail_lines(&path, 2).unwrap()
+        );
+        assert_eq!(content[1000..], tail_lines(&path, 4000).unwrap()[..]);
+        assert_eq!(5000, tail_lines(&path, 10000).unwrap().len());
+        assert!(tail_lines(&path, 0).unwrap().is_empty());
+        std::fs::write(&path, "").unwrap();
+        assert!(tail_lines(&path, 10).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn agent_api_test() {
+        common::setup();
+        let app = test::init_service(App::new().service(api::healthz).service(api::logs)).await;
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        assert_eq!("ok", test::call_and_read_body(&app, req).await);
+
+        let req = test::TestRequest::get()
+            .uri("/logs/..%2F.conf.toml")
+            .to_request();
+        assert_eq!(400, test::call_service(&app, req).await.status().as_u16());
+        let req = test::TestRequest::get()
+            .uri("/logs/nimble1notexists?tail=5")
+            .to_request();
+        assert_eq!(404, test::call_service(&app, req).await.status().as_u16());
+
+        // 节点不可达时返回错误
+        let agent = Agent::new("http://127.0.0.1:1/").unwrap();
+        assert!(agent.healthz().await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn agent_status_test() {
+        common::setup();
+        // 旧版本节点的 /status 没有重启记录
+        let body = r#"{"server_id":1,"address":[],"nvidias":[],"driver":null,"supervisor":"pm2","services":{},"miners":[],"lifecycles":{},"hashrate":{}}"#;
+        let status = serde_json::from_str::<api::AgentStatus>(body).unwrap();
+        assert!(status.restarts.is_empty());
+
+        let mut policy = RestartPolicy::default();
+        let config = Restart::default();
+        policy.decide(&config, "nimble1a", "挖矿进度600秒无变化", Local::now());
+        let status = api::AgentStatus {
+            restarts: policy.records(),
+            ..status
+        };
+        let body = serde_json::to_string(&status).unwrap();
+        assert!(body.contains(r#""decision":"Restart""#));
+        assert!(body.contains("挖矿进度600秒无变化"));
+    }
+}
@@ -0,0 +1,90 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{test as web, App};
+    use monitor::monitor::bootstrap::{self, Allocation};
+    use monitor::server::{
+        self,
+        address::{Deployed, WALLETS_STATE},
+    };
+
+    use crate::common;
+
+    const ADDRESS: [&str; 2] = [
+        "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl",
+        "nimble1uzatqnpkule7f6w9jddwyvwjfjewzevezadqpm",
+    ];
+
+    #[test]
+    fn allocation_test() {
+        common::setup();
+        let path = std::env::temp_dir()
+            .join("monitor_bootstrap")
+            .join("allocation.json");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(None, Allocation::load(&path));
+        let allocation = Allocation {
+            server_id: 7,
+            card_number: 2,
+            address: ADDRESS.iter().map(|a| a.to_string()).collect(),
+            gpus: [(ADDRESS[0].to_string(), "GPU-0".to_string())].into(),
+        };
+        allocation.save(&path).unwrap();
+        assert_eq!(Some(allocation), Allocation::load(&path));
+        // 旧版本保存的文件没有显卡绑定
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"server_id":7,"card_number":2,"address":["{}"]}}"#,
+                ADDRESS[0]
+            ),
+        )
+        .unwrap();
+        assert!(Allocation::load(&path).unwrap().gpus.is_empty());
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(None, Allocation::load(&path));
+    }
+
+    #[actix_web::test]
+    async fn distribute_address_test() {
+        common::setup();
+        {
+            let wallets = Arc::clone(&WALLETS_STATE);
+            let mut wallets_locked = wallets.lock().await;
+            let config = monitor::config::Address {
+                mst_address: Vec::new(),
+                sub_address: ADDRESS.iter().map(|a| a.to_string()).collect(),
+            };
+            wallets_locked.check(&config).await;
+            for address in ADDRESS.iter().rev() {
+                let deploy = Deployed::DEPLOYING {
+                    orderid: 1,
+                    serverid: 7,
+                    sshaddr: None,
+                    sshport: None,
+                };
+                wallets_locked.assgin_server(address, deploy).await.unwrap();
+            }
+        }
+        let app = web::init_service(App::new().service(server::distribute_address)).await;
+        let req = web::TestRequest::get()
+            .uri("/distribute_address/2/7")
+            .to_request();
+        let address: Vec<String> = web::call_and_read_body_json(&app, req).await;
+        assert_eq!(ADDRESS.to_vec(), address);
+        let req = web::TestRequest::get()
+            .uri("/distribute_address/2/8")
+            .to_request();
+        assert_eq!(404, web::call_service(&app, req).await.status().as_u16());
+
+        // 中控不可达时返回错误
+        assert!(
+            bootstrap::fetch("http://127.0.0.1:1/distribute_address", 2, 7)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -8,9 +8,10 @@ mod test {
         io::Read,
     };
 
+    use monitor::config::Strategy;
     use monitor::server::{
         clore::{
-            model::{market::Marketplace, Card},
+            model::{market::Marketplace, Card, CardType},
             Clore,
         },
         ssh::Ssh,
@@ -56,6 +57,22 @@ mod test {
         );
         let result = serde_json::from_str::<Marketplace>(&row);
         assert_eq!(true, result.is_ok());
+
+        let marketplace = result.unwrap();
+        assert!(marketplace.filter(&Strategy::default()).is_empty());
+        let mut strategy = Strategy::default();
+        strategy.market.min_cpus = 2;
+        strategy.market.min_net_down = 0f64;
+        strategy.market.cpu_regex = "(?i)amd|intel".to_string();
+        strategy.market.card_types = vec![CardType::NVIDIA3080];
+        strategy
+            .max_price
+            .insert(CardType::NVIDIA3080.to_string(), 40f64);
+        let cards = marketplace.filter(&strategy);
+        assert_eq!(3, cards.len());
+        assert!(cards
+            .iter()
+            .all(|card| card.card_type == CardType::NVIDIA3080));
     }
 
     #[tokio::test]
@@ -68,7 +85,7 @@ mod test {
         let result = serde_json::from_str::<Marketplace>(&row);
         assert_eq!(true, result.is_ok());
         let model = result.unwrap();
-        let mut cards: Vec<Card> = model.filter();
+        let mut cards: Vec<Card> = model.filter(&Strategy::default());
         cards = cards
             .into_iter()
             .filter(|item| item.card_number == 2)
@@ -2,8 +2,21 @@ pub mod common;
 
 #[cfg(test)]
 mod test {
-    use monitor::config::Config;
-    use std::any::{self, Any};
+    use monitor::config::HEARTBEAT_SECRET_ENV;
+    use monitor::config::{
+        check,
+        secret::{self, Secret},
+        snapshot, Account, Config, ConfigSource, ShutdownAction, Strategy,
+    };
+    use monitor::server::clore::{
+        model::{resent::ResentWeb, Card, CardType},
+        Clore,
+    };
+    use std::{
+        any::{self, Any},
+        collections::HashMap,
+        path::PathBuf,
+    };
 
     use crate::common;
 
@@ -17,15 +30,416 @@ mod test {
         );
     }
 
+    #[test]
+    fn write_atomic_test() {
+        common::setup();
+        let path = write_config("save");
+        let _ = std::fs::remove_dir_all(snapshot::snapshot_dir(&path));
+        let json = path.with_file_name("save.json");
+        let _ = std::fs::remove_file(&json);
+        snapshot::write_atomic(&json, "{}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&json).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+            // 已有文件沿用原权限
+            std::fs::set_permissions(&json, std::fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        snapshot::write_atomic(&json, "[]").unwrap();
+        assert_eq!("[]", std::fs::read_to_string(&json).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&json).unwrap().permissions().mode();
+            assert_eq!(0o644, mode & 0o777);
+        }
+
+        // 内容未变化时不重复保存快照
+        assert!(snapshot::save(&path, 20).unwrap().is_some());
+        assert!(snapshot::save(&path, 20).unwrap().is_none());
+        assert_eq!(1, snapshot::list(&path).unwrap().len());
+    }
+
+    #[test]
+    fn snapshot_rollback_test() {
+        common::setup();
+        let path = write_config("snapshot");
+        let _ = std::fs::remove_dir_all(snapshot::snapshot_dir(&path));
+        let original = std::fs::read_to_string(&path).unwrap();
+
+        let entry = snapshot::save(&path, 2).unwrap().unwrap();
+        snapshot::write_atomic(&path, &original.replacen("keep=20", "keep=2", 1)).unwrap();
+        let changes = snapshot::diff(&path, &entry.name).unwrap();
+        assert_eq!(vec!["snapshot.keep: 20 -> 2"], changes);
+
+        let changes = snapshot::rollback(&path, &entry.name, 2).unwrap();
+        assert_eq!(vec!["snapshot.keep: 2 -> 20"], changes);
+        assert_eq!(original, std::fs::read_to_string(&path).unwrap());
+        assert_eq!(2, snapshot::list(&path).unwrap().len());
+
+        // 超出保留数量时删除最旧的快照
+        std::fs::write(&path, "[monitor]\n").unwrap();
+        snapshot::save(&path, 2).unwrap();
+        let names = snapshot::list(&path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<String>>();
+        assert_eq!(2, names.len());
+        assert!(!names.contains(&entry.name));
+        assert!(snapshot::find(&path, "19700101000000000").is_err());
+    }
+
+    fn write_config(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monitor_config_{}", name));
+        let _ = std::fs::create_dir_all(&dir);
+        let base = std::fs::read_to_string(".conf.toml").unwrap();
+        std::fs::write(dir.join("conf.toml"), base).unwrap();
+        std::fs::write(
+            dir.join("conf.dev.toml"),
+            "[monitor]\napi_report_log=\"http://127.0.0.1:8888/printlnlog\"\n",
+        )
+        .unwrap();
+        dir.join("conf.toml")
+    }
+
+    const SUB_A: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+    const SUB_B: &str = "nimble1uzatqnpkule7f6w9jddwyvwjfjewzevezadqpm";
+
+    #[test]
+    fn load_profile_and_env_test() {
+        common::setup();
+        let path = write_config("profile");
+        let source = ConfigSource {
+            path: Some(path),
+            profile: Some("dev".to_string()),
+            secrets: None,
+        };
+        let mut env = HashMap::new();
+        env.insert(
+            "MONITOR__CLORE__API_TOKEN".to_string(),
+            "env_token".to_string(),
+        );
+        env.insert(
+            "MONITOR__ADDRESS__SUB_ADDRESS".to_string(),
+            format!("{},{}", SUB_A, SUB_B),
+        );
+        env.insert(
+            "MONITOR__MONITOR__ON_SHUTDOWN".to_string(),
+            "stop".to_string(),
+        );
+        let config = Config::load(&source, Some(env)).unwrap();
+        assert_eq!(
+            "http://127.0.0.1:8888/printlnlog",
+            config.monitor.api_report_log
+        );
+        assert_eq!("env_token", config.clore.api_token.expose());
+        assert_eq!(vec![SUB_A, SUB_B], config.address.sub_address);
+        assert_eq!(ShutdownAction::Stop, config.monitor.on_shutdown);
+    }
+
+    #[test]
+    fn load_missing_config_test() {
+        common::setup();
+        let source = ConfigSource {
+            path: Some(PathBuf::from("/nonexistent/.conf.toml")),
+            profile: None,
+            secrets: None,
+        };
+        let result = Config::load(&source, Some(HashMap::new()));
+        assert!(result.is_err());
+
+        let source = ConfigSource {
+            path: Some(write_config("missing_profile")),
+            profile: Some("prod".to_string()),
+            secrets: None,
+        };
+        let result = Config::load(&source, Some(HashMap::new()));
+        assert!(result.unwrap_err().contains("conf.prod.toml"));
+    }
+
     #[tokio::test]
-    async fn export_config_test() {
+    async fn init_missing_config_test() {
         common::setup();
-        let result = Config::import_config();
-        assert_eq!(true, result.is_ok());
-        let config = result.unwrap();
-        assert_eq!(any::TypeId::of::<Config>(), config.type_id());
-        let result = Config::export_config(&config);
+        let source = ConfigSource {
+            path: Some(PathBuf::from("/nonexistent/.conf.toml")),
+            profile: None,
+            secrets: None,
+        };
+        // 加载失败时返回错误,不退出进程,也不替换当前配置来源
+        assert!(Config::init(source).await.is_err());
+        assert!(Config::import_config().is_ok());
+        assert!(Config::default().address.sub_address.is_empty());
+    }
+
+    #[test]
+    fn diff_config_test() {
+        common::setup();
+        let config = Config::import_config().unwrap();
+        let mut other = config.clone();
+        other.clore.api_token = Secret::new("new_token".to_string());
+        other.address.sub_address.push("nimble1new".to_string());
+        other.monitor.api_report_log = "http://127.0.0.1/printlnlog".to_string();
+        let changes = config.diff(&other);
+        assert_eq!(3, changes.len());
+        assert!(changes.contains(&"clore.api_token: ****** -> ******".to_string()));
+        assert!(changes
+            .iter()
+            .any(|change| change.starts_with("address.sub_address: +")));
+        assert!(!changes.iter().any(|change| change.contains("new_token")));
+        assert!(config.diff(&config).is_empty());
+    }
+
+    #[test]
+    fn diff_accounts_test() {
+        common::setup();
+        let mut config = Config::import_config().unwrap();
+        let account = |name: &str, token: &str| Account {
+            name: name.to_string(),
+            api_token: Secret::new(format!("api_{}", token)),
+            web_token: Secret::new(format!("web_{}", token)),
+            ssh_passwd: Secret::new(format!("ssh_{}", token)),
+            ..Default::default()
+        };
+        config.clore.accounts = vec![account("a", "old_secret")];
+        let mut other = config.clone();
+        other.clore.accounts = vec![account("a", "new_secret"), account("b", "added_secret")];
+        let changes = config.diff(&other);
+        assert_eq!(1, changes.len());
+        assert!(changes[0].starts_with("clore.accounts: +"));
+        assert!(changes[0].contains("******"));
+        for change in changes.iter().chain(other.diff(&config).iter()) {
+            assert!(!change.contains("_secret"), "{}", change);
+        }
+
+        // 新增账户列表时同样隐藏
+        config.clore.accounts.clear();
+        let mut value = toml::Value::try_from(&config).unwrap();
+        value["clore"].as_table_mut().unwrap().remove("accounts");
+        let config_file = write_config("diff_accounts");
+        std::fs::write(&config_file, toml::to_string(&value).unwrap()).unwrap();
+        let _ = std::fs::remove_dir_all(snapshot::snapshot_dir(&config_file));
+        let entry = snapshot::save(&config_file, 20).unwrap().unwrap();
+        value["clore"].as_table_mut().unwrap().insert(
+            "accounts".to_string(),
+            toml::Value::try_from(&other.clore.accounts).unwrap(),
+        );
+        std::fs::write(&config_file, toml::to_string(&value).unwrap()).unwrap();
+        let changes = snapshot::diff(&config_file, &entry.name).unwrap();
+        assert!(!changes.is_empty());
+        for change in changes.iter() {
+            assert!(!change.contains("_secret"), "{}", change);
+        }
+    }
+
+    #[test]
+    fn secret_redact_test() {
+        common::setup();
+        let config = Config::import_config().unwrap();
+        let token = config.clore.api_token.expose().clone();
+        assert!(!format!("{:?}", config).contains(&token));
+        assert_eq!("******", config.clore.ssh_passwd.to_string());
+        let body = secret::redact(&config.clore);
+        assert!(!body.contains(&token));
+        assert!(body.contains(r#""api_token":"******""#));
+
+        // ssh密码同时通过 ssh_password 字段和容器环境变量提交,打印时均需脱敏
+        let resent = ResentWeb::new(
+            1,
+            Secret::new("ssh_secret".to_string()),
+            Secret::new("web_secret".to_string()),
+            String::new(),
+        );
+        assert_eq!(
+            Some(&"ssh_secret".to_string()),
+            resent.env.get("SSH_PASSWORD")
+        );
+        assert_eq!(
+            Some(&"ssh_secret".to_string()),
+            resent.env.get("WEBUI_PASSWORD")
+        );
+        let json = serde_json::to_string(&resent).unwrap();
+        assert!(json.contains(r#""ssh_password":"ssh_secret""#));
+        assert!(!secret::redact(&resent).contains("ssh_secret"));
+    }
+
+    #[test]
+    fn order_env_test() {
+        common::setup();
+        let card = Card {
+            server_id: 7,
+            avg_score: 0f64,
+            price_demand: 0f64,
+            avg_price_demand: 0f64,
+            price_spot: 0f64,
+            avg_price_spot: 0f64,
+            mrl: 0,
+            card_number: 2,
+            rented: false,
+            card_type: CardType::NVIDIA4090,
+        };
+        let address = vec!["nimble1a".to_string(), "nimble1b".to_string()];
+        // 租用的节点通过下单时的环境变量获得心跳密钥
+        let secret = Secret::new("heartbeat_secret".to_string());
+        let mut resent = ResentWeb::new(7, Secret::default(), Secret::default(), String::new());
+        resent
+            .env
+            .extend(Clore::order_env(&card, &address, &secret));
+        assert_eq!(
+            Some(&"heartbeat_secret".to_string()),
+            resent.env.get(HEARTBEAT_SECRET_ENV)
+        );
+        assert_eq!(
+            Some(&"nimble1a-nimble1b".to_string()),
+            resent.env.get("ADDRESS")
+        );
+        let json = serde_json::to_string(&resent).unwrap();
+        assert!(json.contains("heartbeat_secret"));
+        assert!(!secret::redact(&resent).contains("heartbeat_secret"));
+
+        let env = Clore::order_env(&card, &address, &Secret::default());
+        assert!(!env.contains_key(HEARTBEAT_SECRET_ENV));
+
+        // 节点加载配置时由该环境变量覆盖 monitor.heartbeat.secret
+        let source = ConfigSource {
+            path: Some(write_config("order_env")),
+            profile: None,
+            secrets: None,
+        };
+        let env = HashMap::from([(
+            HEARTBEAT_SECRET_ENV.to_string(),
+            "heartbeat_secret".to_string(),
+        )]);
+        let config = Config::load(&source, Some(env)).unwrap();
+        assert_eq!("heartbeat_secret", config.monitor.heartbeat.secret.expose());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_file_test() {
+        use std::os::unix::fs::PermissionsExt;
+        common::setup();
+        let path = write_config("secrets");
+        let secrets = path.with_file_name("secrets.toml");
+        std::fs::write(&secrets, "[clore]\nweb_token=\"file_token\"\n").unwrap();
+        let source = ConfigSource {
+            path: Some(path),
+            profile: None,
+            secrets: Some(secrets.clone()),
+        };
+
+        std::fs::set_permissions(&secrets, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let result = Config::load(&source, Some(HashMap::new()));
+        assert!(result.unwrap_err().contains("chmod 600"));
+
+        std::fs::set_permissions(&secrets, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let config = Config::load(&source, Some(HashMap::new())).unwrap();
+        assert_eq!("file_token", config.clore.web_token.expose());
+    }
+
+    #[test]
+    fn strategy_validate_test() {
+        common::setup();
+        let config = Config::import_config().unwrap();
+        assert!(config.strategy.validate().is_empty());
+        assert_eq!(30, config.strategy.interval);
+        // 自动取消订单需显式开启
+        assert!(!Strategy::default().auto_cancel);
+
+        let mut strategy = Strategy {
+            chunk_size: 0,
+            ..Default::default()
+        };
+        strategy.market.gpu_regex = "(4090".to_string();
+        strategy.hashrate.low = 30f32;
+        strategy.max_price.insert("NVIDIA9999".to_string(), 1f64);
+        assert_eq!(4, strategy.validate().len());
+    }
+
+    #[test]
+    fn accounts_test() {
+        common::setup();
+        let mut config = Config::import_config().unwrap();
+        let accounts = config.clore.get_accounts();
+        assert_eq!(1, accounts.len());
+        assert_eq!("default", config.clore.account_of("nimble1any"));
+
+        let sub_address = config.address.sub_address[0].clone();
+        config.clore.accounts = vec![
+            Account {
+                name: "a".to_string(),
+                api_token: Secret::new("token_a".to_string()),
+                ..Default::default()
+            },
+            Account {
+                name: "b".to_string(),
+                addresses: vec![sub_address.clone()],
+                ..Default::default()
+            },
+        ];
+        assert!(config.validate().is_ok());
+        assert_eq!("b", config.clore.account_of(&sub_address));
+        assert_eq!("a", config.clore.account_of("nimble1any"));
+        let b = config.clore.get_account("b").unwrap();
+        assert_eq!(config.clore.api_token, b.api_token);
+        assert_eq!(Some(config.clore.command.clone()), b.command);
+
+        config.clore.accounts[0].addresses = vec![sub_address, "nimble1unknown".to_string()];
+        assert_eq!(2, config.validate().unwrap_err().split('\n').count());
+    }
+
+    #[test]
+    fn nimble_address_test() {
+        assert!(check::is_nimble_address(SUB_A));
+        assert!(check::is_nimble_address(SUB_B));
+        // 改动一位后校验和不通过
+        assert!(!check::is_nimble_address(&SUB_A.replace("ufl", "ufm")));
+        assert!(!check::is_nimble_address(&SUB_A.to_uppercase()));
+        assert!(!check::is_nimble_address(
+            &SUB_A.replace("nimble", "cosmos")
+        ));
+        assert!(!check::is_nimble_address("nimble1a"));
+    }
+
+    #[test]
+    fn command_placeholder_test() {
+        let command = "bash run.sh {server_id} {card_number} {address} ${HOME} {serverid}";
+        assert_eq!(vec!["serverid"], check::unknown_placeholders(command));
+    }
+
+    #[test]
+    fn config_check_test() {
+        common::setup();
+        let path = write_config("check");
+        let source = ConfigSource {
+            path: Some(path.clone()),
+            profile: None,
+            secrets: None,
+        };
+        assert!(Config::check(&source).is_empty());
+
+        let base = std::fs::read_to_string(".conf.toml").unwrap();
+        let broken = base
+            .replacen("[monitor]", "[monitor]\nintervel=30", 1)
+            .replacen("https://api.clore.ai/", "https://api.clore.ai", 1)
+            .replacen("backend=\"auto\"", "backend=\"fixture\"", 1)
+            .replacen(SUB_B, &SUB_B.replace("dqpm", "dqpq"), 1)
+            .replacen(
+                "sub_address = [",
+                &format!("sub_address = [\n\"{}\",", SUB_A),
+                1,
+            );
+        std::fs::write(&path, broken).unwrap();
+        let problems = Config::check(&source);
+        assert!(problems.iter().any(|p| p.contains("monitor.intervel")));
+        assert!(problems.iter().any(|p| p.contains("clore.api_host")));
+        assert!(problems.iter().any(|p| p.contains("nimble地址")));
+        assert!(problems.iter().any(|p| p.contains("地址重复")));
+        assert!(problems.iter().any(|p| p.contains("monitor.gpu.fixture")));
+        assert!(Config::load(&source, Some(HashMap::new())).is_err());
 
-        assert!(result.is_ok());
+        std::fs::write(&path, "[monitor\n").unwrap();
+        assert_eq!(1, Config::check(&source).len());
     }
 }
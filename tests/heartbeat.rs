@@ -0,0 +1,57 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use monitor::monitor::heartbeat::{self, Heartbeat, MinerReport};
+    use monitor::monitor::lifecycle::MinerState;
+
+    use crate::common;
+
+    const ADDRESS: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+
+    fn sample(timestamp: i64) -> Heartbeat {
+        let mut miners = BTreeMap::new();
+        miners.insert(
+            ADDRESS.to_string(),
+            MinerReport {
+                state: MinerState::Training,
+                hashrate: Some(12.5),
+                last_progress: timestamp - 30,
+            },
+        );
+        Heartbeat {
+            server_id: 7,
+            addresses: vec![ADDRESS.to_string()],
+            gpus: vec!["GPU-13d44c72-a798-c126-54cb-98e543beadd3".to_string()],
+            miners,
+            version: "0.1.0".to_string(),
+            timestamp,
+            host: None,
+            telemetry: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn verify_test() {
+        common::setup();
+        let now = 1_717_000_000;
+        let body = serde_json::to_vec(&sample(now)).unwrap();
+        let signature = heartbeat::sign("secret", &body);
+        assert_eq!(
+            sample(now),
+            heartbeat::verify("secret", &body, &signature, now + 10).unwrap()
+        );
+        // 密钥不一致、内容被改动或签名格式错误
+        assert!(heartbeat::verify("other", &body, &signature, now).is_err());
+        let mut tampered = body.clone();
+        tampered[10] ^= 1;
+        assert!(heartbeat::verify("secret", &tampered, &signature, now).is_err());
+        assert!(heartbeat::verify("secret", &body, "xyz", now).is_err());
+        assert!(heartbeat::verify("", &body, &heartbeat::sign("", &body), now).is_err());
+        // 过期的心跳不接受
+        let late = now + heartbeat::MAX_SKEW + 1;
+        assert!(heartbeat::verify("secret", &body, &signature, late).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use monitor::config::{Host, HostAction};
+    use monitor::monitor::host::{self, HostIssue, HostScanner};
+
+    use crate::common;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn host_scanner_test() {
+        common::setup();
+        let root = std::env::temp_dir().join("monitor_host_proc");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("meminfo"),
+            "MemTotal:       65536000 kB\nMemFree:  100 kB\nMemAvailable:    1024000 kB\nSwapTotal:  0 kB\nSwapFree:  0 kB\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("loadavg"), "40.50 20.00 10.25 3/900 12345\n").unwrap();
+        std::fs::write(root.join("cpuinfo"), "processor\t: 0\n\nprocessor\t: 1\n").unwrap();
+        std::fs::write(root.join("vmstat"), "nr_free_pages 1\noom_kill 3\n").unwrap();
+
+        let health = HostScanner::new(&root).sample(&root);
+        assert_eq!(65536000 * 1024, health.mem_total);
+        assert_eq!(1024000 * 1024, health.mem_available);
+        assert_eq!([40.5, 20.0, 10.25], health.load);
+        assert_eq!(2, health.cpus);
+        assert_eq!(3, health.oom_kills);
+        assert!(health.disk_total > 0);
+
+        let config = Host {
+            min_disk_free: health.disk_total / MB + 1,
+            ..Default::default()
+        };
+        let mut previous = health.clone();
+        previous.oom_kills = 2;
+        assert_eq!(
+            vec![
+                HostIssue::DiskFull,
+                HostIssue::LowMemory,
+                HostIssue::Overload,
+                HostIssue::OomKill
+            ],
+            host::issues(&config, &health, Some(&previous))
+        );
+        // 首次采样无法判断 OOM
+        assert!(!host::issues(&config, &health, None).contains(&HostIssue::OomKill));
+    }
+
+    #[test]
+    fn host_actions_test() {
+        common::setup();
+        let config = Host {
+            on_low_memory: vec![HostAction::StopMiners, HostAction::Flag],
+            ..Default::default()
+        };
+        let issues = vec![HostIssue::DiskFull, HostIssue::LowMemory];
+        assert_eq!(
+            vec![HostAction::Flag, HostAction::StopMiners],
+            host::actions(&config, &issues, &[])
+        );
+        // 默认不清理缓存
+        assert!(Host::default().cache_dirs.is_empty());
+        assert!(!Host::default().on_disk_full.contains(&HostAction::Clean));
+        // 持续存在的异常不重复处理,OOM 每次都处理
+        assert!(host::actions(&config, &issues, &issues).is_empty());
+        assert_eq!(
+            vec![HostAction::Flag],
+            host::actions(&config, &[HostIssue::OomKill], &[HostIssue::OomKill])
+        );
+
+        let mut health = host::HostHealth {
+            issues: vec![HostIssue::DiskFull],
+            ..Default::default()
+        };
+        assert!(!health.stops_miners(&config));
+        health.issues.push(HostIssue::LowMemory);
+        assert!(health.stops_miners(&config));
+    }
+
+    #[test]
+    fn cache_paths_test() {
+        common::setup();
+        let root = std::env::temp_dir().join("monitor_host_cache");
+        let _ = std::fs::remove_dir_all(&root);
+        let home = root.join("home");
+        let workdir = root.join("work");
+        for dir in ["home/.cache/pip", "home/.ssh", "work/cache", "other"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let cache_dirs = vec![
+            "~/.cache/pip".to_string(),
+            "~/.cache/missing".to_string(),
+            "~/.cache".to_string(),
+            "~/.cache/../.ssh".to_string(),
+            "~/.ssh".to_string(),
+            workdir.join("cache").display().to_string(),
+            workdir.display().to_string(),
+            root.join("other").display().to_string(),
+        ];
+        assert_eq!(
+            vec![
+                home.join(".cache/pip").canonicalize().unwrap(),
+                workdir.join("cache").canonicalize().unwrap()
+            ],
+            host::cache_paths(&cache_dirs, &home, &workdir)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Local};
+    use monitor::log::{Massage, MsgType};
+    use monitor::monitor::lifecycle::{
+        Lifecycle, MinerEvent, MinerState, INSTALL_STALL_AFTER, STALL_AFTER,
+    };
+    use monitor::monitor::policy::{Decision, RESTART_POLICY};
+    use monitor::monitor::{Monitor, MONITOR};
+
+    use crate::common;
+
+    #[test]
+    fn lifecycle_test() {
+        common::setup();
+        let start = Local::now();
+        let at = |seconds: i64| start + Duration::seconds(seconds);
+        let stall_after = Duration::seconds(STALL_AFTER);
+        let install_after = Duration::seconds(INSTALL_STALL_AFTER);
+        let mut lifecycle = Lifecycle::new(start);
+        assert_eq!(MinerState::Installing, lifecycle.state);
+
+        let preparing = MinerEvent::Preparing {
+            percent: "10%".to_string(),
+        };
+        assert_eq!(None, lifecycle.apply(&preparing, at(1)));
+        let downloading = MinerEvent::Downloading {
+            percent: "50%".to_string(),
+        };
+        assert_eq!(
+            Some((MinerState::Installing, MinerState::Downloading)),
+            lifecycle.apply(&downloading, at(2))
+        );
+        let progress = MinerEvent::Progress {
+            percent: "3%".to_string(),
+            hashrate: 13.5,
+        };
+        assert_eq!(
+            Some((MinerState::Downloading, MinerState::Training)),
+            lifecycle.apply(&progress, at(3))
+        );
+        assert_eq!(Some(13.5), lifecycle.hashrate);
+        assert_eq!(at(3), lifecycle.since);
+        assert_eq!(None, lifecycle.apply(&progress, at(4)));
+        assert_eq!(at(3), lifecycle.since);
+
+        // 长时间无日志
+        assert_eq!(None, lifecycle.tick(at(5), stall_after, install_after));
+        assert_eq!(
+            Some((MinerState::Training, MinerState::Stalled)),
+            lifecycle.tick(at(4 + STALL_AFTER + 1), stall_after, install_after)
+        );
+        assert_eq!(
+            None,
+            lifecycle.tick(at(4 + STALL_AFTER * 2), stall_after, install_after)
+        );
+
+        // 日志持续输出但进度不变
+        let mut stuck = Lifecycle::new(start);
+        let downloading = |percent: &str| MinerEvent::Downloading {
+            percent: percent.to_string(),
+        };
+        stuck.apply(&downloading("50% 5/10"), at(1));
+        stuck.apply(&downloading("50% 6/10"), at(100));
+        for seconds in (200..=100 + STALL_AFTER).step_by(100) {
+            stuck.apply(&downloading("50% 6/10"), at(seconds));
+        }
+        assert_eq!(at(100), stuck.last_progress);
+        assert_eq!(
+            Some((MinerState::Downloading, MinerState::Stalled)),
+            stuck.tick(at(101 + STALL_AFTER), stall_after, install_after)
+        );
+        // 验算阶段没有进度,按事件判断
+        stuck.apply(&MinerEvent::Verifying, at(1000));
+        stuck.apply(&MinerEvent::Verifying, at(1000 + STALL_AFTER));
+        assert_eq!(
+            None,
+            stuck.tick(at(1001 + STALL_AFTER), stall_after, install_after)
+        );
+
+        lifecycle.apply(&MinerEvent::Verifying, at(2000));
+        assert_eq!(MinerState::Verifying, lifecycle.state);
+        lifecycle.apply(&MinerEvent::Completed, at(2001));
+        assert_eq!(MinerState::Idle, lifecycle.state);
+        // 空闲等待任务时不判定为卡住
+        assert_eq!(None, lifecycle.tick(at(9000), stall_after, install_after));
+
+        let failed = MinerEvent::Failed("Failed to init particle".to_string());
+        assert_eq!(
+            Some((MinerState::Idle, MinerState::Crashed)),
+            lifecycle.apply(&failed, at(9001))
+        );
+        assert_eq!("Crashed", lifecycle.state.to_string());
+    }
+
+    #[test]
+    fn stall_restart_test() {
+        common::setup();
+        let start = Local::now();
+        let at = |seconds: i64| start + Duration::seconds(seconds);
+        let stall_after = Duration::seconds(STALL_AFTER);
+        let install_after = Duration::seconds(INSTALL_STALL_AFTER);
+        let mut lifecycle = Lifecycle::new(start);
+
+        // 安装阶段使用更长的判定时长
+        assert_eq!(
+            None,
+            lifecycle.tick(at(STALL_AFTER + 1), stall_after, install_after)
+        );
+        let progress = MinerEvent::Progress {
+            percent: "3%".to_string(),
+            hashrate: 13.5,
+        };
+        lifecycle.apply(&progress, at(10));
+        let stalled = at(10 + STALL_AFTER + 1);
+        assert_eq!(
+            Some((MinerState::Training, MinerState::Stalled)),
+            lifecycle.tick(stalled, stall_after, install_after)
+        );
+
+        // 重启后回到安装阶段重新计时
+        assert_eq!(
+            Some((MinerState::Stalled, MinerState::Installing)),
+            lifecycle.restarted(stalled)
+        );
+        assert_eq!(None, lifecycle.progress);
+        let second = stalled + install_after;
+        assert_eq!(None, lifecycle.tick(second, stall_after, install_after));
+        // 重启后仍无进展,再次判定为卡住
+        assert_eq!(
+            Some((MinerState::Installing, MinerState::Stalled)),
+            lifecycle.tick(second + Duration::seconds(1), stall_after, install_after)
+        );
+        // 重启后恢复训练,相同进度也算进展
+        lifecycle.restarted(second + Duration::seconds(1));
+        lifecycle.apply(&progress, second + Duration::seconds(2));
+        assert_eq!(MinerState::Training, lifecycle.state);
+        assert_eq!(Some("3%".to_string()), lifecycle.progress);
+    }
+
+    #[tokio::test]
+    async fn stall_cooldown_test() {
+        common::setup();
+        let address = "stall_cooldown_test";
+        let config = Monitor::get_config().await;
+        let start = Local::now();
+        {
+            let mut monitor = MONITOR.lock().await;
+            let progress = MinerEvent::Progress {
+                percent: "3%".to_string(),
+                hashrate: 13.5,
+            };
+            monitor.on_event(address, &progress).await;
+            let stall_after = config.stall_after.unwrap_or(STALL_AFTER);
+            monitor
+                .check_stalls(start + Duration::seconds(stall_after + 1))
+                .await;
+            assert_eq!(MinerState::Stalled, monitor.get_lifecycles()[address].state);
+        }
+
+        // 刚重启过,重启请求处于冷却期,不重启也不重置阶段
+        let decision =
+            RESTART_POLICY
+                .lock()
+                .await
+                .decide(&config.restart, address, "挖矿进度无变化", start);
+        assert_eq!(Decision::Restart, decision);
+        let msg = Massage {
+            address: address.to_string(),
+            msg_type: MsgType::RESTART,
+            body: "Training阶段挖矿进度无变化".to_string(),
+        };
+        Monitor::restart(Some(0), &msg).await;
+        let monitor = MONITOR.lock().await;
+        assert_eq!(MinerState::Stalled, monitor.get_lifecycles()[address].state);
+    }
+}
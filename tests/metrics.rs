@@ -0,0 +1,65 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use monitor::log::RunLogs;
+    use monitor::monitor::metrics::{self, Metrics};
+
+    use crate::common;
+
+    const ADDRESS: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+
+    #[test]
+    fn render_test() {
+        common::setup();
+        let mut metrics = Metrics::default();
+        metrics.family("nimble_gpus", "gauge", "识别到的显卡数量");
+        metrics.sample("nimble_gpus", &[], 2f64);
+        metrics.family("nimble_hashrate", "gauge", "最近一次训练算力(it/s)");
+        metrics.sample(
+            "nimble_hashrate",
+            &[("address", ADDRESS), ("gpu", "0\"\\")],
+            12.5,
+        );
+        let expected = format!(
+            "# HELP nimble_gpus 识别到的显卡数量\n\
+             # TYPE nimble_gpus gauge\n\
+             nimble_gpus 2\n\
+             # HELP nimble_hashrate 最近一次训练算力(it/s)\n\
+             # TYPE nimble_hashrate gauge\n\
+             nimble_hashrate{{address=\"{}\",gpu=\"0\\\"\\\\\"}} 12.5\n",
+            ADDRESS
+        );
+        assert_eq!(expected, metrics.render());
+    }
+
+    #[test]
+    fn task_counts_test() {
+        common::setup();
+        let path = std::env::temp_dir().join("monitor_metrics_my_logs.json");
+        let record = |status: &str| {
+            format!(
+                r#"{{"WalletAddr":"{}","CompletedTime":"2024-06-01 12:00:00","TrainRuntime":1.5,"Status":"{}"}}"#,
+                ADDRESS, status
+            )
+        };
+        let body = format!(
+            "[{},{},{}]",
+            record("Success"),
+            record("Success"),
+            record("Failed")
+        );
+        std::fs::write(&path, body).unwrap();
+        let logs = RunLogs::load(&path).unwrap();
+        let counts = metrics::task_counts(&logs);
+        assert_eq!(
+            Some(&2),
+            counts.get(&(ADDRESS.to_string(), "success".to_string()))
+        );
+        assert_eq!(
+            Some(&1),
+            counts.get(&(ADDRESS.to_string(), "failed".to_string()))
+        );
+        assert!(RunLogs::load(&path.with_extension("missing")).is_err());
+    }
+}
@@ -1,10 +1,94 @@
 pub mod common;
 #[cfg(test)]
 mod test {
-    use tracing::info;
+    use std::path::PathBuf;
 
-    #[tokio::test]
-    async fn nvidia_test() {
-        crate::common::setup();
+    use monitor::config::Gpu;
+    use monitor::monitor::nvidia::{self, FixtureBackend, GeForce, GpuBackend, GpuBackendKind};
+    use monitor::server::clore::model::CardType;
+
+    use crate::common;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn card_type_test() {
+        common::setup();
+        let cases = [
+            ("NVIDIA GeForce RTX 4090", CardType::NVIDIA4090),
+            ("NVIDIA GeForce RTX 4070 Ti", CardType::NVIDIA4070TI),
+            ("NVIDIA GeForce RTX 4080 SUPER", CardType::NVIDIA4080S),
+            ("NVIDIA GeForce GTX 1080 Ti", CardType::NVIDIA1080TI),
+        ];
+        for (name, card_type) in cases {
+            assert_eq!(card_type, CardType::from_name(name), "{}", name);
+        }
+        // 未收录的型号不再 panic
+        for name in ["NVIDIA RTX A6000", "NVIDIA GeForce RTX 5090", "UNKNOWN", ""] {
+            assert_eq!(
+                CardType::UNKNOWN(name.to_string()),
+                CardType::from_name(name)
+            );
+        }
+    }
+
+    #[test]
+    fn smi_fixture_test() {
+        common::setup();
+        let backend = nvidia::new(&Gpu {
+            backend: GpuBackendKind::Fixture,
+            fixture: Some(fixture("nvidia-smi-L.txt")),
+        });
+        assert_eq!("fixture", backend.name());
+        let nvidias = backend.discover().unwrap();
+        assert_eq!(4, nvidias.len());
+        assert_eq!(None, nvidias.driver);
+        assert_eq!(
+            GeForce::CARD {
+                id: 3,
+                uuid: "GPU-8a7e2b1c-0d3f-4e5a-9b6c-7d8e9f0a1b2c".to_string(),
+                card_type: CardType::NVIDIA4070S,
+                name: "NVIDIA GeForce RTX 4070 Ti SUPER".to_string(),
+                memory: None,
+                bus_id: None,
+            },
+            nvidias[3]
+        );
+        assert!(matches!(&nvidias[2], GeForce::ERROR(e) if e.contains("0000:41:00.0")));
+        assert_eq!(3, nvidias.get_normal_nvidias().len());
+    }
+
+    #[test]
+    fn nvml_fixture_test() {
+        common::setup();
+        let nvidias = FixtureBackend::new(fixture("nvml.json"))
+            .discover()
+            .unwrap();
+        let driver = nvidias.driver.clone().unwrap();
+        assert_eq!("550.54.14", driver.version);
+        assert_eq!("12.4", driver.cuda);
+        assert_eq!(3, nvidias.len());
+        assert!(matches!(nvidias[1], GeForce::ERROR(_)));
+        assert!(matches!(
+            &nvidias[2],
+            GeForce::CARD {
+                id: 2,
+                card_type: CardType::UNKNOWN(_),
+                memory: Some(49140),
+                ..
+            }
+        ));
+
+        // 文件不存在或未配置
+        assert!(FixtureBackend::new(fixture("missing.txt"))
+            .discover()
+            .is_err());
+        let backend = nvidia::new(&Gpu {
+            backend: GpuBackendKind::Fixture,
+            fixture: None,
+        });
+        assert!(backend.discover().is_err());
     }
 }
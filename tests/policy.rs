@@ -0,0 +1,64 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Local};
+    use monitor::config::Restart;
+    use monitor::monitor::policy::{Decision, RestartPolicy};
+
+    use crate::common;
+
+    const ADDRESS: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+
+    #[test]
+    fn restart_policy_test() {
+        common::setup();
+        let config = Restart {
+            cooldown: 300,
+            max_per_hour: 2,
+            stop_on_escalate: true,
+        };
+        let mut policy = RestartPolicy::default();
+        let start = Local::now();
+        let at = |minutes: i64| start + Duration::minutes(minutes);
+
+        assert_eq!(
+            Decision::Restart,
+            policy.decide(&config, ADDRESS, "算力过低", at(0))
+        );
+        assert_eq!(
+            Decision::Cooldown { remaining: 240 },
+            policy.decide(&config, ADDRESS, "算力过低", at(1))
+        );
+        assert_eq!(
+            Decision::Restart,
+            policy.decide(&config, ADDRESS, "算力过低", at(10))
+        );
+        // 一小时内超过最大重启次数
+        assert_eq!(
+            Decision::Escalate,
+            policy.decide(&config, ADDRESS, "算力过低", at(20))
+        );
+        assert!(policy.is_escalated(ADDRESS, at(30)));
+        assert_eq!(
+            Decision::Suppressed,
+            policy.decide(&config, ADDRESS, "算力过低", at(30))
+        );
+        // 不同地址互不影响
+        assert_eq!(
+            Decision::Restart,
+            policy.decide(&config, "nimble1other", "Failed to init particle", at(30))
+        );
+        // 暂停期结束后重新计数
+        assert!(!policy.is_escalated(ADDRESS, at(81)));
+        assert_eq!(
+            Decision::Restart,
+            policy.decide(&config, ADDRESS, "算力过低", at(81))
+        );
+
+        let records = policy.records();
+        assert_eq!(7, records.len());
+        assert_eq!("Failed to init particle", records[5].reason);
+        assert_eq!(Some(&3), policy.restarts_total().get(ADDRESS));
+    }
+}
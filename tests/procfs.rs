@@ -0,0 +1,104 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use monitor::monitor::procfs::ProcScanner;
+
+    use crate::common;
+
+    const ADDRESS: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+
+    fn write_proc(
+        root: &Path,
+        pid: u32,
+        ppid: u32,
+        comm: &str,
+        cmdline: &[&str],
+        environ: &[&str],
+    ) {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let stat = format!(
+            "{} ({}) S {} {} 0 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 0",
+            pid, comm, ppid, pid, 360000
+        );
+        std::fs::write(dir.join("stat"), stat).unwrap();
+        let join = |items: &[&str]| {
+            items
+                .iter()
+                .map(|item| format!("{}\0", item))
+                .collect::<String>()
+        };
+        std::fs::write(dir.join("cmdline"), join(cmdline)).unwrap();
+        std::fs::write(dir.join("environ"), join(environ)).unwrap();
+    }
+
+    fn fake_proc() -> PathBuf {
+        let root = std::env::temp_dir().join("monitor_fake_proc");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("stat"), "cpu  1 2 3\nbtime 1700000000\n").unwrap();
+        std::fs::create_dir_all(root.join("self")).unwrap();
+        write_proc(&root, 1, 0, "systemd", &["/sbin/init"], &[]);
+        write_proc(
+            &root,
+            100,
+            1,
+            "PM2 v5.3.1: God",
+            &["PM2 v5.3.1: God Daemon"],
+            &[],
+        );
+        let cmd = format!("CUDA_VISIBLE_DEVICES=4 make run addr={}", ADDRESS);
+        write_proc(
+            &root,
+            200,
+            100,
+            "sh",
+            &["/bin/sh", "-c", &cmd],
+            &["pm_id=3", "CUDA_VISIBLE_DEVICES=4"],
+        );
+        write_proc(
+            &root,
+            201,
+            200,
+            "make",
+            &["make", "run"],
+            &["CUDA_VISIBLE_DEVICES=4"],
+        );
+        write_proc(
+            &root,
+            202,
+            201,
+            "python3 (main)",
+            &["python3", "execute.py", ADDRESS],
+            &["CUDA_VISIBLE_DEVICES=4"],
+        );
+        write_proc(&root, 300, 1, "python3", &["python3", "./execute.py"], &[]);
+        root
+    }
+
+    #[test]
+    fn procfs_miners_test() {
+        common::setup();
+        let scanner = ProcScanner::new(fake_proc());
+        assert_eq!(6, scanner.processes().unwrap().len());
+
+        let miners = scanner.miners().unwrap();
+        assert_eq!(1, miners.len());
+        let miner = &miners[0];
+        assert_eq!(202, miner.pid);
+        assert_eq!(ADDRESS, miner.address);
+        assert_eq!(Some("4".to_string()), miner.cuda_visible_devices);
+        assert_eq!(Some(3), miner.pm2_id);
+        assert_eq!(1700003600, miner.start_time.unwrap().timestamp());
+    }
+
+    #[test]
+    fn procfs_missing_root_test() {
+        let scanner = ProcScanner::new("/nonexistent/proc");
+        assert!(scanner.miners().is_err());
+        assert!(!ProcScanner::default().processes().unwrap().is_empty());
+    }
+}
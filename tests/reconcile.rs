@@ -0,0 +1,296 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use monitor::monitor::nvidia::{FixtureBackend, GpuBackend};
+    use monitor::monitor::{
+        nvidia::GeForce,
+        once,
+        procfs::MinerProcess,
+        reconcile::{self, Step},
+        supervisor::{DryRun, Status, Supervisor},
+    };
+    use monitor::server::clore::model::CardType;
+
+    use crate::common;
+
+    const ADDRESS: [&str; 3] = [
+        "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl",
+        "nimble1uzatqnpkule7f6w9jddwyvwjfjewzevezadqpm",
+        "nimble1t2gxjyu5zgznf5cltet2qknqpafg83fhzu9d4u",
+    ];
+
+    // nvidia-smi -L 中的显卡id可能不连续
+    fn nvidias() -> Vec<GeForce> {
+        [4, 0, 2]
+            .into_iter()
+            .map(|id| GeForce::CARD {
+                id,
+                uuid: format!("GPU-{}", id),
+                card_type: CardType::NVIDIA4090,
+                name: "NVIDIA GeForce RTX 4090".to_string(),
+                memory: Some(24564),
+                bus_id: None,
+            })
+            .collect()
+    }
+
+    fn miner(pid: u32, address: &str, device: &str) -> MinerProcess {
+        MinerProcess {
+            pid,
+            start_time: None,
+            address: address.to_string(),
+            cuda_visible_devices: Some(device.to_string()),
+            pm2_id: None,
+        }
+    }
+
+    #[test]
+    fn assign_test() {
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let assignments = reconcile::assign(&address, &nvidias(), &BTreeMap::new());
+        let gpus = assignments.iter().map(|a| a.gpu_id).collect::<Vec<u32>>();
+        assert_eq!(vec![0, 2, 4], gpus);
+        assert_eq!("GPU-4", assignments[2].uuid);
+
+        let assignments = reconcile::assign(&address[..2], &nvidias(), &BTreeMap::new());
+        assert_eq!(2, assignments.len());
+    }
+
+    /// 已绑定的地址不随显卡序号移动,绑定的显卡识别失败时不分配
+    #[test]
+    fn bound_assign_test() {
+        common::setup();
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let bound = reconcile::assign(&address, &nvidias(), &BTreeMap::new())
+            .into_iter()
+            .map(|a| (a.address, a.uuid))
+            .collect::<BTreeMap<String, String>>();
+
+        // 显卡0识别失败
+        let mut nvidias = nvidias();
+        nvidias[1] = GeForce::ERROR("识别显卡错误".to_string());
+        let assignments = reconcile::assign(&address, &nvidias, &bound);
+        let pairs = assignments
+            .iter()
+            .map(|a| (a.address.as_str(), a.gpu_id))
+            .collect::<Vec<(&str, u32)>>();
+        assert_eq!(vec![(ADDRESS[1], 2), (ADDRESS[2], 4)], pairs);
+        // 没有绑定时后面的地址会整体前移
+        let shifted = reconcile::assign(&address, &nvidias, &BTreeMap::new());
+        assert_eq!(ADDRESS[0], shifted[0].address);
+        assert_eq!(2, shifted[0].gpu_id);
+
+        // 运行在显卡2、4上的进程不需要处理,显卡0的地址暂不拉起
+        let miners = vec![miner(11, ADDRESS[1], "2"), miner(12, ADDRESS[2], "4")];
+        assert!(reconcile::plan(&assignments, &nvidias, &miners).is_healthy());
+
+        // 新地址只分配到未绑定的显卡
+        let mut bound = bound;
+        bound.remove(ADDRESS[1]);
+        let assignments = reconcile::assign(&address, &nvidias, &bound);
+        let new = assignments
+            .iter()
+            .find(|a| a.address == ADDRESS[1])
+            .unwrap();
+        assert_eq!(2, new.gpu_id);
+        assert!(!assignments.iter().any(|a| a.address == ADDRESS[0]));
+    }
+
+    #[test]
+    fn plan_test() {
+        common::setup();
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let assignments = reconcile::assign(&address, &nvidias(), &BTreeMap::new());
+
+        let healthy = vec![
+            miner(10, ADDRESS[0], "0"),
+            miner(11, ADDRESS[1], "GPU-2"),
+            miner(12, ADDRESS[2], "4"),
+        ];
+        assert!(reconcile::plan(&assignments, &nvidias(), &healthy).is_healthy());
+
+        let miners = vec![
+            miner(10, ADDRESS[0], "0"),
+            miner(11, ADDRESS[0], "0"),
+            miner(12, ADDRESS[2], "2"),
+            miner(13, ADDRESS[2], "7"),
+        ];
+        let plan = reconcile::plan(&assignments, &nvidias(), &miners);
+        assert!(!plan.is_healthy());
+        assert_eq!(
+            vec![13],
+            plan.stray.iter().map(|m| m.pid).collect::<Vec<u32>>()
+        );
+
+        // 同一显卡上重复的地址
+        let gpu0 = &plan.gpus.iter().find(|gpu| gpu.gpu_id == 0).unwrap();
+        assert!(matches!(&gpu0.steps[..], [Step::Stop { pid: 11, .. }]));
+        // 运行在错误显卡上的进程先停止,再拉起分配的地址
+        let gpu2 = &plan.gpus.iter().find(|gpu| gpu.gpu_id == 2).unwrap();
+        assert!(matches!(
+            &gpu2.steps[..],
+            [Step::Stop { pid: 12, .. }, Step::Start { .. }]
+        ));
+        // 空闲显卡
+        let gpu4 = &plan.gpus.iter().find(|gpu| gpu.gpu_id == 4).unwrap();
+        assert_eq!(
+            vec![Step::Start {
+                address: ADDRESS[2].to_string()
+            }],
+            gpu4.steps
+        );
+    }
+
+    #[test]
+    fn incomplete_inventory_plan_test() {
+        common::setup();
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let miners = vec![miner(10, ADDRESS[0], "0"), miner(11, ADDRESS[1], "GPU-2")];
+
+        // 显卡检测失败,不停止任何进程
+        let plan = reconcile::plan(&[], &[], &miners);
+        assert!(plan.stray.is_empty());
+        assert!(plan.is_healthy());
+
+        // 显卡2读取失败,其上的进程不当作未知显卡停止
+        let mut nvidias = nvidias();
+        nvidias.retain(|nvidia| !matches!(nvidia, GeForce::CARD { id: 2, .. }));
+        nvidias.push(GeForce::ERROR(
+            "Unable to determine the device handle for GPU0000:02:00.0: Unknown Error".to_string(),
+        ));
+        let assignments = reconcile::assign(&address, &nvidias, &BTreeMap::new());
+        let plan = reconcile::plan(&assignments, &nvidias, &miners);
+        assert!(plan.stray.is_empty());
+        let gpu0 = plan.gpus.iter().find(|gpu| gpu.gpu_id == 0).unwrap();
+        assert!(gpu0.steps.is_empty());
+        assert!(plan.gpus.iter().all(|gpu| gpu
+            .steps
+            .iter()
+            .all(|step| !matches!(step, Step::Stop { .. }))));
+    }
+
+    /// 只允许查询,执行操作时 panic
+    struct Fake;
+
+    impl Supervisor for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn start(&self, _: u32, _: &str) -> Result<(), String> {
+            panic!("dry-run 不应拉起服务")
+        }
+
+        fn restart(&self, _: u32) -> Result<(), String> {
+            panic!("dry-run 不应重启服务")
+        }
+
+        fn stop(&self, _: u32) -> Result<(), String> {
+            panic!("dry-run 不应停止服务")
+        }
+
+        fn status(&self, card_number: u32) -> Result<Status, String> {
+            match card_number {
+                0 => Ok(Status::Online),
+                4 => Ok(Status::Stopped),
+                _ => Ok(Status::Missing),
+            }
+        }
+
+        fn owner(&self, pid: u32, _: Option<u32>) -> Option<u32> {
+            (pid == 12).then_some(2)
+        }
+
+        fn kill(&self, _: u32) -> Result<(), String> {
+            panic!("dry-run 不应结束进程")
+        }
+    }
+
+    #[test]
+    fn dry_run_report_test() {
+        common::setup();
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let assignments = reconcile::assign(&address, &nvidias(), &BTreeMap::new());
+        let miners = vec![miner(12, ADDRESS[0], "2"), miner(13, ADDRESS[2], "7")];
+        let plan = reconcile::plan(&assignments, &nvidias(), &miners);
+        let supervisor = DryRun::new(Box::new(Fake));
+        let lines = once::report(&supervisor, &address, &nvidias(), &miners, &plan);
+        let expected = [
+            format!("  {} -> 显卡2", ADDRESS[1]),
+            "  nimble4 Stopped".to_string(),
+            "    -> kill 13".to_string(),
+            format!("  显卡2(GPU-2) {}:", ADDRESS[1]),
+            format!("    停止进程12({}):应运行在显卡0上", ADDRESS[0]),
+            "    -> fake 停止并删除服务 nimble2".to_string(),
+            format!("    -> fake 创建并启动服务 nimble2,地址:{}", ADDRESS[1]),
+            "    -> fake 重启服务 nimble4".to_string(),
+        ];
+        for line in expected.iter() {
+            assert!(lines.contains(line), "{}\n{}", line, lines.join("\n"));
+        }
+        // 服务运行中时只等待进程启动
+        let gpu0 = lines
+            .iter()
+            .position(|line| line.starts_with("  显卡0"))
+            .unwrap();
+        assert_eq!(
+            format!("    显卡空闲,拉起地址:{}", ADDRESS[0]),
+            lines[gpu0 + 1]
+        );
+        assert!(lines[gpu0 + 2].starts_with("  显卡2"));
+        assert!(supervisor.take().is_empty());
+    }
+
+    /// 按录制的显卡信息处理,识别失败的显卡不分配地址,无法确认其上的进程所在显卡已不存在,不停止
+    #[test]
+    fn fixture_reconcile_test() {
+        common::setup();
+        let nvidias = FixtureBackend::new("tests/fixtures/nvidia-smi-L.txt".into())
+            .discover()
+            .unwrap();
+        let address = ADDRESS
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let assignments = reconcile::assign(&address, &nvidias, &BTreeMap::new());
+        let gpus = assignments.iter().map(|a| a.gpu_id).collect::<Vec<u32>>();
+        assert_eq!(vec![0, 1, 3], gpus);
+
+        let miners = vec![miner(10, ADDRESS[0], "0"), miner(13, ADDRESS[2], "2")];
+        let plan = reconcile::plan(&assignments, &nvidias, &miners);
+        assert!(plan.stray.is_empty());
+
+        let supervisor = DryRun::new(Box::new(Fake));
+        let lines = once::report(&supervisor, &address, &nvidias, &miners, &plan);
+        let expected = [
+            "  识别显卡错误:Unable to determine the device handle for GPU 0000:41:00.0: Unknown Error"
+                .to_string(),
+            format!("  {} -> 显卡3", ADDRESS[2]),
+            format!("    -> fake 创建并启动服务 nimble1,地址:{}", ADDRESS[1]),
+            format!("    -> fake 创建并启动服务 nimble3,地址:{}", ADDRESS[2]),
+        ];
+        for line in expected.iter() {
+            assert!(lines.contains(line), "{}\n{}", line, lines.join("\n"));
+        }
+        assert!(!lines.contains(&"    -> kill 13".to_string()));
+        assert!(supervisor.take().is_empty());
+    }
+}
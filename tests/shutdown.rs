@@ -0,0 +1,73 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    use monitor::log::{Log, Logs, MsgType, LOG_CHANNEL};
+    use monitor::monitor::{lifecycle::MinerEvent, task};
+    use monitor::shutdown;
+
+    use crate::common;
+
+    #[tokio::test]
+    async fn shutdown_test() {
+        common::setup();
+        assert!(!shutdown::is_shutdown());
+        assert!(!shutdown::sleep(Duration::from_millis(10)).await);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        let supervisor = tokio::spawn(task::supervise("测试", move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                shutdown::wait().await;
+            }
+        }));
+        // 日志读取任务:同一阶段的进度等待定时汇总后发送
+        let path = std::env::temp_dir().join("monitor_shutdown/nimble1shutdown.txt");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let line = |percent: u32| {
+            format!(
+                "Downloading data:  {}%|█████     | {}/10 [00:01<00:01,  5.00it/s]\n",
+                percent * 10,
+                percent
+            )
+        };
+        std::fs::write(&path, line(5) + &line(6)).unwrap();
+        let tailer = tokio::spawn(Logs::read_log_file(Log {
+            filename: path,
+            spawn: true,
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+
+        // 收到退出通知后任务退出且不再重启
+        shutdown::trigger();
+        // 日志读取任务退出前发出尚未汇总的进度
+        tokio::time::timeout(Duration::from_secs(1), tailer)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut receiver = LOG_CHANNEL.1.lock().await;
+        let mut percents = Vec::new();
+        while let Ok(msg) = receiver.try_recv() {
+            if let MsgType::EVENT(MinerEvent::Downloading { percent }) = msg.msg_type {
+                percents.push(percent);
+            }
+        }
+        assert_eq!(vec!["50% 5/10", "60% 6/10"], percents);
+        assert!(shutdown::is_shutdown());
+        tokio::time::timeout(Duration::from_secs(1), supervisor)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+        assert!(shutdown::sleep(Duration::from_secs(60)).await);
+    }
+}
@@ -0,0 +1,193 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{os::unix::process::CommandExt, path::PathBuf, time::Duration};
+
+    use monitor::monitor::{
+        procfs::ProcScanner,
+        supervisor::{
+            native::{Backoff, MinerState, NativeSupervisor},
+            Status, Supervisor, SystemdSupervisor,
+        },
+    };
+
+    use crate::common;
+
+    const ADDRESS: &str = "nimble12eq5tfzlmjpvqq0k20vxzqwd3fsnxv9rvkzufl";
+
+    #[test]
+    fn systemd_supervisor_test() {
+        common::setup();
+        let root = std::env::temp_dir().join("monitor_systemd");
+        let _ = std::fs::remove_dir_all(&root);
+        let proc_root = root.join("proc");
+        std::fs::create_dir_all(proc_root.join("200")).unwrap();
+        std::fs::create_dir_all(proc_root.join("300")).unwrap();
+        std::fs::write(
+            proc_root.join("200/cgroup"),
+            "0::/user.slice/user-0.slice/user@0.service/app.slice/nimble4.service\n",
+        )
+        .unwrap();
+        std::fs::write(
+            proc_root.join("300/cgroup"),
+            "0::/user.slice/session-1.scope\n",
+        )
+        .unwrap();
+
+        let supervisor =
+            SystemdSupervisor::with_dirs(root.join("units"), "/root/clore", &proc_root);
+        assert_eq!("systemd", supervisor.name());
+        let unit = supervisor.unit(4, ADDRESS);
+        assert!(unit.contains("Environment=CUDA_VISIBLE_DEVICES=4"));
+        assert!(unit.contains(&format!("make run addr={}", ADDRESS)));
+        assert!(unit.contains(&format!("append:/root/clore/logs/{}.txt", ADDRESS)));
+
+        assert_eq!(Ok(Status::Missing), supervisor.status(4));
+        // 地址拼接进 ExecStart,不合法时不写入服务定义
+        assert!(supervisor.start(4, "nimble1x' ; rm -rf ~ ; '").is_err());
+        assert!(!root.join("units/nimble4.service").exists());
+        assert_eq!(Some(4), supervisor.owner(200, None));
+        assert_eq!(None, supervisor.owner(300, Some(1)));
+        assert_eq!(None, supervisor.owner(400, None));
+    }
+
+    fn native(name: &str, script: &str) -> (PathBuf, NativeSupervisor) {
+        let workdir = std::env::temp_dir().join(format!("monitor_native_{}", name));
+        let _ = std::fs::remove_dir_all(&workdir);
+        std::fs::create_dir_all(&workdir).unwrap();
+        let backoff = Backoff {
+            base: Duration::from_millis(10),
+            max: Duration::from_millis(40),
+            window: Duration::from_secs(60),
+            limit: 3,
+            stable: Duration::from_secs(60),
+            pause: Duration::from_secs(1),
+        };
+        let args = vec!["-c".to_string(), script.to_string()];
+        let supervisor = NativeSupervisor::with_command(&workdir, &workdir, "sh", args, backoff);
+        (workdir, supervisor)
+    }
+
+    #[tokio::test]
+    async fn native_supervisor_test() {
+        common::setup();
+        let (workdir, supervisor) =
+            native("run", "echo {address} $CUDA_VISIBLE_DEVICES; exec sleep 30");
+        assert_eq!(Ok(Status::Missing), supervisor.status(1));
+        supervisor.start(1, ADDRESS).unwrap();
+        assert!(supervisor.start(1, ADDRESS).is_err());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(Ok(Status::Online), supervisor.status(1));
+        let pid = supervisor.pid(1).unwrap();
+        assert_eq!(Some(1), supervisor.owner(pid, None));
+        let pid_file = std::fs::read_to_string(workdir.join("run/nimble1.pid")).unwrap();
+        assert_eq!(
+            format!("{} {} {}", pid, start_ticks(pid), ADDRESS),
+            pid_file
+        );
+        let log = std::fs::read_to_string(supervisor.log_file(ADDRESS)).unwrap();
+        assert_eq!(format!("{} 1\n", ADDRESS), log);
+
+        supervisor.stop(1).unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(Ok(Status::Missing), supervisor.status(1));
+        assert!(is_dead(pid));
+        assert!(!workdir.join("run/nimble1.pid").exists());
+    }
+
+    fn start_ticks(pid: u32) -> u64 {
+        ProcScanner::default().process(pid).unwrap().start_ticks
+    }
+
+    fn is_dead(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map(|stat| stat.contains(") Z "))
+            .unwrap_or(true)
+    }
+
+    #[tokio::test]
+    async fn native_crash_loop_test() {
+        common::setup();
+        let (workdir, supervisor) = native("crash", "exit 1");
+        supervisor.start(0, ADDRESS).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(Some(MinerState::CrashLoop), supervisor.state(0));
+        // 暂停期间调和不处理,结束后由调和重新拉起
+        assert_eq!(Ok(Status::CrashLoop), supervisor.status(0));
+        assert!(!workdir.join("run/nimble0.pid").exists());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(Ok(Status::Stopped), supervisor.status(0));
+
+        // 手动重启清空崩溃记录
+        supervisor.restart(0).unwrap();
+        assert_eq!(Ok(Status::Online), supervisor.status(0));
+        supervisor.stop(0).unwrap();
+        assert_eq!(Ok(Status::Missing), supervisor.status(0));
+    }
+
+    #[tokio::test]
+    async fn native_adopt_test() {
+        common::setup();
+        let (workdir, supervisor) = native("adopt", "exec sleep 30");
+        // 上次运行留下的进程及失效的pid文件
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let ticks = start_ticks(child.id());
+        std::fs::create_dir_all(workdir.join("run")).unwrap();
+        std::fs::write(
+            workdir.join("run/nimble2.pid"),
+            format!("{} {} {}", child.id(), ticks, ADDRESS),
+        )
+        .unwrap();
+        std::fs::write(
+            workdir.join("run/nimble3.pid"),
+            format!("{} 0 {}", u32::MAX, ADDRESS),
+        )
+        .unwrap();
+        // pid 已被其他进程复用:启动时间不一致
+        std::fs::write(
+            workdir.join("run/nimble4.pid"),
+            format!("{} {} {}", child.id(), ticks + 1, ADDRESS),
+        )
+        .unwrap();
+        // 旧格式没有启动时间,无法确认是否为挖矿进程
+        std::fs::write(
+            workdir.join("run/nimble5.pid"),
+            format!("{} {}", child.id(), ADDRESS),
+        )
+        .unwrap();
+        // dry-run 只查看,不接管也不删除pid文件
+        let viewer = NativeSupervisor::with_command(
+            &workdir,
+            &workdir,
+            "sh",
+            Vec::new(),
+            Backoff::default(),
+        );
+        viewer.inspect();
+        assert_eq!(Ok(Status::Online), viewer.status(2));
+        assert_eq!(Ok(Status::Missing), viewer.status(3));
+        for card_number in [3, 4, 5] {
+            let pid_file = format!("run/nimble{}.pid", card_number);
+            assert!(workdir.join(pid_file).exists());
+        }
+
+        supervisor.adopt();
+        assert_eq!(Some(child.id()), supervisor.pid(2));
+        assert_eq!(Ok(Status::Online), supervisor.status(2));
+        for card_number in [3, 4, 5] {
+            assert_eq!(Ok(Status::Missing), supervisor.status(card_number));
+            let pid_file = format!("run/nimble{}.pid", card_number);
+            assert!(!workdir.join(pid_file).exists());
+        }
+
+        supervisor.stop(2).unwrap();
+        assert!(child.wait().is_ok());
+        assert!(!workdir.join("run/nimble2.pid").exists());
+    }
+}
@@ -0,0 +1,37 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    use monitor::monitor::task;
+
+    use crate::common;
+
+    #[tokio::test]
+    async fn supervise_restart_test() {
+        common::setup();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        let supervisor = tokio::spawn(task::supervise("测试", move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                // 第一次运行 panic,之后一直运行
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("任务异常");
+                }
+                std::future::pending::<()>().await;
+            }
+        }));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+        supervisor.abort();
+    }
+}
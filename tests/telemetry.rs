@@ -0,0 +1,69 @@
+pub mod common;
+
+#[cfg(test)]
+mod test {
+    use monitor::monitor::telemetry::{GpuSample, GpuSamples};
+
+    use crate::common;
+
+    const UUID: &str = "GPU-13d44c72-a798-c126-54cb-98e543beadd3";
+
+    fn sample(timestamp: i64, temperature: u32, throttle: &[&str]) -> GpuSample {
+        GpuSample {
+            timestamp,
+            temperature,
+            power: 300f32,
+            power_limit: 450f32,
+            sm_util: 100,
+            mem_util: 40,
+            memory_used: 20000 + timestamp as u64,
+            throttle: throttle.iter().map(|reason| reason.to_string()).collect(),
+            ecc_errors: None,
+        }
+    }
+
+    #[test]
+    fn ring_buffer_test() {
+        common::setup();
+        let mut samples = GpuSamples::new(3);
+        for timestamp in 0..5 {
+            samples.push(UUID, sample(timestamp, 60, &[]));
+        }
+        assert_eq!(4, samples.latest(UUID).unwrap().timestamp);
+        assert_eq!(3, samples.summary(UUID).unwrap().samples);
+        samples.set_capacity(1);
+        assert_eq!(1, samples.summary(UUID).unwrap().samples);
+        assert_eq!(4, samples.latest(UUID).unwrap().timestamp);
+        // 拔掉的显卡不再上报
+        samples.retain(&[]);
+        assert!(samples.summaries().is_empty());
+    }
+
+    #[test]
+    fn summary_test() {
+        common::setup();
+        let mut samples = GpuSamples::new(10);
+        samples.push(UUID, sample(1, 70, &["GPU_IDLE"]));
+        samples.push(UUID, sample(2, 86, &["SW_THERMAL_SLOWDOWN"]));
+        samples.push(
+            UUID,
+            sample(3, 88, &["SW_THERMAL_SLOWDOWN", "SW_POWER_CAP"]),
+        );
+        let summary = samples.summary(UUID).unwrap();
+        assert_eq!(3, summary.samples);
+        assert_eq!(88, summary.temperature_max);
+        assert!((summary.temperature_avg - 81.333).abs() < 0.01);
+        assert_eq!(20003, summary.memory_used_max);
+        // 空闲不算降频
+        assert_eq!(None, summary.throttled.get("GPU_IDLE"));
+        assert_eq!(Some(&2), summary.throttled.get("SW_THERMAL_SLOWDOWN"));
+        assert_eq!(Some(&1), summary.throttled.get("SW_POWER_CAP"));
+        assert!(summary.is_throttled());
+        assert!(summary.to_string().contains("SW_THERMAL_SLOWDOWN"));
+
+        samples.push(UUID, sample(4, 60, &[]));
+        samples.push(UUID, sample(5, 60, &[]));
+        assert!(!samples.summary(UUID).unwrap().is_throttled());
+        assert_eq!(None, samples.summary("GPU-missing"));
+    }
+}